@@ -0,0 +1,439 @@
+//! Arena module contains data structures and procedures related
+//! to parsing values into storage shared by all of their nodes.
+use crate::lexer::{SpannedRef, TokenRef};
+use crate::parser::Bencode;
+use crate::{parser, value_spans, Error};
+
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Integer(i64),
+    /// Range of bytes of the tree.
+    ByteString(Range<usize>),
+    /// Range of child indices of the tree.
+    List(Range<usize>),
+    /// Range of child indices of the tree, alternating between keys and values.
+    Dictionary(Range<usize>),
+}
+
+/// Buffers shared by all trees parsed by the same [`ArenaParser`]. Every
+/// tree occupies contiguous part of each buffer, and indices and ranges
+/// stored in its nodes are relative to the start of that part.
+#[derive(Debug, Default)]
+struct Storage {
+    nodes: Vec<Node>,
+    children: Vec<usize>,
+    bytes: Vec<u8>,
+    /// Indices of children gathered for unfinished containers.
+    scratch: Vec<usize>,
+    /// Unfinished containers.
+    stack: Vec<Frame>,
+}
+
+/// Container which is being built, along with the length of the scratch
+/// buffer before its first child was gathered.
+#[derive(Debug)]
+struct Frame {
+    index: usize,
+    mark: usize,
+    dictionary: bool,
+    expects_key: bool,
+}
+
+impl Storage {
+    /// Appends tree built from given tokens and returns ranges it occupies
+    /// in the node, child and byte buffers. Tokens are walked in a single
+    /// loop, with unfinished containers kept on explicit stack. On failure
+    /// the buffers are left as they were.
+    fn build(&mut self, spans: &[SpannedRef<'_>]) -> Result<[Range<usize>; 3], parser::Error> {
+        let starts = (self.nodes.len(), self.children.len(), self.bytes.len());
+        let res = self.build_nodes(spans, starts);
+        if res.is_err() {
+            self.nodes.truncate(starts.0);
+            self.children.truncate(starts.1);
+            self.bytes.truncate(starts.2);
+            self.scratch.clear();
+            self.stack.clear();
+        }
+        res.map(|()| {
+            [
+                starts.0..self.nodes.len(),
+                starts.1..self.children.len(),
+                starts.2..self.bytes.len(),
+            ]
+        })
+    }
+
+    fn build_nodes(
+        &mut self,
+        spans: &[SpannedRef<'_>],
+        (node_start, child_start, byte_start): (usize, usize, usize),
+    ) -> Result<(), parser::Error> {
+        for (_, token) in spans {
+            let index = self.nodes.len() - node_start;
+            let expects_key = matches!(self.stack.last(), Some(frame) if frame.expects_key);
+            match token {
+                TokenRef::End => match self.stack.pop() {
+                    Some(frame) if expects_key || !frame.dictionary => {
+                        let start = self.children.len() - child_start;
+                        self.children.extend(self.scratch.drain(frame.mark..));
+                        let range = start..self.children.len() - child_start;
+                        self.nodes[node_start + frame.index] = match frame.dictionary {
+                            true => Node::Dictionary(range),
+                            false => Node::List(range),
+                        };
+                        if self.stack.is_empty() {
+                            return Ok(());
+                        }
+                        self.adopt(frame.index);
+                        continue;
+                    }
+                    _ => return Err(parser::Error::InvalidEndToken),
+                },
+                TokenRef::ByteString(_) => {}
+                _ if expects_key => return Err(parser::Error::InvalidDictionaryKey),
+                TokenRef::List | TokenRef::Dictionary
+                    if self.stack.len() >= parser::DEFAULT_MAX_DEPTH =>
+                {
+                    return Err(parser::Error::DepthLimitExceeded)
+                }
+                TokenRef::List | TokenRef::Dictionary => {
+                    self.nodes.push(Node::List(0..0));
+                    let dictionary = *token == TokenRef::Dictionary;
+                    self.stack.push(Frame {
+                        index,
+                        mark: self.scratch.len(),
+                        dictionary,
+                        expects_key: dictionary,
+                    });
+                    continue;
+                }
+                TokenRef::Integer(_) => {}
+            }
+            match token {
+                TokenRef::Integer(val) => self.nodes.push(Node::Integer(*val)),
+                TokenRef::ByteString(val) => {
+                    let start = self.bytes.len() - byte_start;
+                    self.bytes.extend_from_slice(val);
+                    let range = start..self.bytes.len() - byte_start;
+                    self.nodes.push(Node::ByteString(range));
+                }
+                _ => unreachable!("containers and end tokens are handled above"),
+            }
+            if self.stack.is_empty() {
+                return Ok(());
+            }
+            self.adopt(index);
+        }
+        match self.stack.last() {
+            Some(frame) if frame.dictionary => Err(parser::Error::NoEndDictionary),
+            Some(_) => Err(parser::Error::NoEndList),
+            None => Err(parser::Error::NoTokens),
+        }
+    }
+
+    /// Gathers node at given index as the next child of the innermost
+    /// unfinished container.
+    fn adopt(&mut self, index: usize) {
+        self.scratch.push(index);
+        if let Some(frame) = self.stack.last_mut() {
+            frame.expects_key = frame.dictionary && !frame.expects_key;
+        }
+    }
+}
+
+/// Tree parsed by [`ArenaParser`]. Instead of allocating every node
+/// separately, all nodes, links between them and contents of byte
+/// strings are kept in three flat buffers, shared by all trees parsed
+/// by the same parser.
+#[derive(Clone)]
+pub struct BencodeArena {
+    storage: Rc<RefCell<Storage>>,
+    nodes: Range<usize>,
+    children: Range<usize>,
+    bytes: Range<usize>,
+}
+
+impl BencodeArena {
+    /// Returns number of nodes in the tree.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Copies the tree into owned `Bencode`.
+    pub fn to_bencode(&self) -> Bencode {
+        let storage = self.storage.borrow();
+        TreeRef {
+            nodes: &storage.nodes[self.nodes.clone()],
+            children: &storage.children[self.children.clone()],
+            bytes: &storage.bytes[self.bytes.clone()],
+        }
+        .node_to_bencode(0)
+    }
+}
+
+impl PartialEq for BencodeArena {
+    fn eq(&self, other: &Self) -> bool {
+        let left = self.storage.borrow();
+        let right = other.storage.borrow();
+        left.nodes[self.nodes.clone()] == right.nodes[other.nodes.clone()]
+            && left.children[self.children.clone()] == right.children[other.children.clone()]
+            && left.bytes[self.bytes.clone()] == right.bytes[other.bytes.clone()]
+    }
+}
+
+impl fmt::Debug for BencodeArena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let storage = self.storage.borrow();
+        f.debug_struct("BencodeArena")
+            .field("nodes", &&storage.nodes[self.nodes.clone()])
+            .field("children", &&storage.children[self.children.clone()])
+            .field("bytes", &&storage.bytes[self.bytes.clone()])
+            .finish()
+    }
+}
+
+/// Parts of the shared buffers occupied by a single tree.
+struct TreeRef<'a> {
+    nodes: &'a [Node],
+    children: &'a [usize],
+    bytes: &'a [u8],
+}
+
+impl<'a> TreeRef<'a> {
+    fn node_to_bencode(&self, index: usize) -> Bencode {
+        match &self.nodes[index] {
+            Node::Integer(val) => Bencode::Integer(*val),
+            Node::ByteString(range) => Bencode::ByteString(self.bytes[range.clone()].to_vec()),
+            Node::List(range) => Bencode::List(
+                self.children[range.clone()]
+                    .iter()
+                    .map(|&child| self.node_to_bencode(child))
+                    .collect(),
+            ),
+            Node::Dictionary(range) => parser::dictionary(
+                self.children[range.clone()]
+                    .chunks(2)
+                    .map(|pair| match &self.nodes[pair[0]] {
+                        Node::ByteString(key) => (
+                            self.bytes[key.clone()].to_vec(),
+                            self.node_to_bencode(pair[1]),
+                        ),
+                        _ => unreachable!("dictionary keys are always byte strings"),
+                    })
+                    .collect::<HashMap<Vec<u8>, Bencode>>(),
+            ),
+        }
+    }
+}
+
+const FIRST_CHUNK_LEN: usize = 8;
+
+/// Fixed number of slots for parsed trees, followed by twice as big chunk.
+struct Chunk {
+    slots: Box<[OnceCell<BencodeArena>]>,
+    next: OnceCell<Box<Chunk>>,
+}
+
+impl Chunk {
+    fn new(len: usize) -> Self {
+        Chunk {
+            slots: (0..len).map(|_| OnceCell::new()).collect(),
+            next: OnceCell::new(),
+        }
+    }
+}
+
+/// Parses values into arena, which keeps them alive for as long as the
+/// parser itself. All parsed trees store their nodes in buffers shared
+/// by the whole arena, which avoids allocating each node, or even each
+/// tree, separately.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{ArenaParser, Bencode};
+///
+/// let parser = ArenaParser::new();
+/// let first = parser.parse(b"li1ei2ee").unwrap();
+/// let second = parser.parse(b"4:spam").unwrap();
+///
+/// assert_eq!(first.to_bencode(), Bencode::List(vec![Bencode::Integer(1), Bencode::Integer(2)]));
+/// assert_eq!(second.to_bencode(), Bencode::ByteString("spam".into()));
+/// ```
+pub struct ArenaParser {
+    storage: Rc<RefCell<Storage>>,
+    head: Chunk,
+    len: Cell<usize>,
+}
+
+impl Default for ArenaParser {
+    fn default() -> Self {
+        ArenaParser::new()
+    }
+}
+
+impl ArenaParser {
+    /// Creates empty arena.
+    pub fn new() -> Self {
+        ArenaParser {
+            storage: Rc::default(),
+            head: Chunk::new(FIRST_CHUNK_LEN),
+            len: Cell::new(0),
+        }
+    }
+
+    /// Parses given slice of bytes, which has to contain exactly one value,
+    /// into tree stored in the arena. Byte strings are copied from the data
+    /// straight into the shared buffer. Lists and dictionaries can be nested
+    /// at most [`DEFAULT_MAX_DEPTH`](parser::DEFAULT_MAX_DEPTH) levels deep.
+    pub fn parse<'arena>(&'arena self, data: &[u8]) -> Result<&'arena BencodeArena, Error> {
+        let spans = value_spans(data)?;
+        let [nodes, children, bytes] = self
+            .storage
+            .borrow_mut()
+            .build(&spans)
+            .map_err(Error::Parser)?;
+        let tree = BencodeArena {
+            storage: Rc::clone(&self.storage),
+            nodes,
+            children,
+            bytes,
+        };
+
+        let mut offset = self.len.get();
+        let mut chunk = &self.head;
+        while offset >= chunk.slots.len() {
+            offset -= chunk.slots.len();
+            let len = chunk.slots.len() * 2;
+            chunk = chunk.next.get_or_init(|| Box::new(Chunk::new(len)));
+        }
+        self.len.set(self.len.get() + 1);
+        Ok(chunk.slots[offset].get_or_init(|| tree))
+    }
+
+    /// Returns number of trees stored in the arena.
+    pub fn tree_count(&self) -> usize {
+        self.len.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, instrument};
+
+    #[test]
+    fn test_parse_wide_structure() {
+        let data = {
+            let entries = (0..1000)
+                .map(|i| {
+                    format!(
+                        "d4:name{}:file{}6:lengthi{}ee",
+                        4 + i.to_string().len(),
+                        i,
+                        i
+                    )
+                })
+                .collect::<String>();
+            format!("l{}e", entries)
+        };
+
+        let parser = ArenaParser::new();
+        let tree = parser.parse(data.as_bytes()).unwrap();
+
+        assert_eq!(tree.node_count(), 1 + 1000 * 5);
+        assert_eq!(tree.to_bencode(), from_bytes(data.as_bytes()).unwrap());
+
+        // Nodes of the tree do not cost allocations of their own.
+        let (_, owned) = instrument::count(|| from_bytes(data.as_bytes()).unwrap());
+        let (_, shared) = instrument::count(|| parser.parse(data.as_bytes()).unwrap());
+        assert!(owned.allocations > 1000, "{:?}", owned);
+        assert!(shared.allocations < 10, "{:?}", shared);
+    }
+
+    #[test]
+    fn test_parse_many_trees() {
+        let parser = ArenaParser::new();
+        let trees = (0..100)
+            .map(|i| parser.parse(format!("i{}e", i).as_bytes()).unwrap())
+            .collect::<Vec<&BencodeArena>>();
+
+        assert_eq!(parser.tree_count(), 100);
+        for (i, tree) in trees.into_iter().enumerate() {
+            assert_eq!(tree.to_bencode(), Bencode::Integer(i as i64));
+        }
+
+        // Trees share buffers, which grow less and less often, so only
+        // tokens of every tree are allocated separately.
+        let data = b"l4:spami1ee";
+        let (_, counts) = instrument::count(|| {
+            for _ in 0..1000 {
+                parser.parse(data).unwrap();
+            }
+        });
+        assert!(counts.allocations < 1100, "{:?}", counts);
+        assert!(counts.reallocations < 100, "{:?}", counts);
+        assert_eq!(
+            parser.parse(data).unwrap().to_bencode(),
+            from_bytes(data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let parser = ArenaParser::new();
+        assert_eq!(
+            parser.parse(b"li1e"),
+            Err(Error::Parser(parser::Error::NoEndList))
+        );
+        assert_eq!(
+            parser.parse(b"d1:ai1ei2ee"),
+            Err(Error::Parser(parser::Error::InvalidDictionaryKey))
+        );
+        assert_eq!(
+            parser.parse(b"d1:ae"),
+            Err(Error::Parser(parser::Error::InvalidEndToken))
+        );
+        assert_eq!(
+            parser.parse(b"i1ei2e"),
+            Err(Error::TrailingData { offset: 3 })
+        );
+        assert_eq!(parser.tree_count(), 0);
+
+        // Failed attempts leave nothing behind in the shared buffers.
+        let tree = parser.parse(b"l4:spame").unwrap();
+        assert_eq!(tree.node_count(), 2);
+        assert_eq!(tree.to_bencode(), from_bytes(b"l4:spame").unwrap());
+        assert_eq!(parser.storage.borrow().nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_non_utf8_key() {
+        let data = b"d1:ai1e1:\xffli2eee";
+        let parser = ArenaParser::new();
+        let tree = parser.parse(data).unwrap();
+        assert!(matches!(tree.to_bencode(), Bencode::DictionaryBytes(_)));
+        assert_eq!(tree.to_bencode(), from_bytes(data).unwrap());
+    }
+
+    #[test]
+    fn test_parse_deeply_nested() {
+        let nested = |depth: usize| format!("{}i1e{}", "d1:a".repeat(depth), "e".repeat(depth));
+        let parser = ArenaParser::new();
+
+        let data = format!("l{}e", nested(parser::DEFAULT_MAX_DEPTH - 1));
+        assert!(parser.parse(data.as_bytes()).is_ok());
+
+        let data = format!("l{}e", nested(50_000));
+        assert_eq!(
+            parser.parse(data.as_bytes()),
+            Err(Error::Parser(parser::Error::DepthLimitExceeded))
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Base64 module contains procedures related to embedding
+//! bencoded values in text, with the standard base64 alphabet.
+use crate::parser::Bencode;
+use crate::{from_bytes, Error};
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PAD: u8 = b'=';
+
+/// Encodes given bytes, padding the output to a multiple of four characters.
+fn encode(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.chunks(3).len() * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, &byte)| acc | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                res.push(PAD as char);
+            }
+        }
+    }
+    res
+}
+
+/// Decodes given string, with or without padding. Returns offset
+/// of the first character that cannot be decoded, on failure.
+fn decode(s: &str) -> Result<Vec<u8>, usize> {
+    let bytes = s.as_bytes();
+    let padding = bytes
+        .iter()
+        .rev()
+        .take(2)
+        .take_while(|&&c| c == PAD)
+        .count();
+    let data = &bytes[..bytes.len() - padding];
+
+    let mut res = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for (offset, &c) in data.iter().enumerate() {
+        let sextet = ALPHABET.iter().position(|&a| a == c).ok_or(offset)?;
+        acc = acc << 6 | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    if padding > 0 && bytes.len() % 4 != 0 {
+        return Err(data.len());
+    }
+    if data.len() % 4 == 1 {
+        return Err(data.len() - 1);
+    }
+    Ok(res)
+}
+
+impl Bencode {
+    /// Parses value encoded in given base64 string. Returns
+    /// `Error::InvalidBase64` if the string is not valid base64.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let value = Bencode::from_base64("aTQyZQ==").unwrap();
+    /// assert_eq!(value, Bencode::Integer(42));
+    /// ```
+    pub fn from_base64(s: &str) -> Result<Bencode, Error> {
+        let data = decode(s).map_err(|offset| Error::InvalidBase64 { offset })?;
+        from_bytes(&data)
+    }
+
+    /// Serializes `Bencode` and encodes the result with base64.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// assert_eq!(Bencode::Integer(42).to_base64(), "aTQyZQ==");
+    /// ```
+    pub fn to_base64(&self) -> String {
+        let mut res = Vec::with_capacity(self.encoded_len());
+        self.write_to(&mut res).unwrap();
+        encode(&res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"", ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (&[0xff, 0xfe, 0x00], "//4A"),
+        ];
+        for (bytes, text) in cases {
+            assert_eq!(encode(bytes), *text);
+            assert_eq!(decode(text), Ok(bytes.to_vec()));
+        }
+        assert_eq!(decode("Zm8"), Ok(b"fo".to_vec()));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(decode("Zm9v!A=="), Err(4));
+        assert_eq!(decode("Zm9vY"), Err(4));
+        assert_eq!(decode("Zm8=="), Err(3));
+        assert_eq!(
+            Bencode::from_base64("aTQy ZQ=="),
+            Err(Error::InvalidBase64 { offset: 4 })
+        );
+    }
+
+    #[test]
+    fn test_base64_round_trip() {
+        let value = Bencode::dict_from_iter(vec![
+            (
+                "bytes".to_string(),
+                Bencode::ByteString(vec![0xff, 0x00, 0x7f]),
+            ),
+            (
+                "list".to_string(),
+                Bencode::List(vec![
+                    Bencode::Integer(-7),
+                    Bencode::ByteString("spam".into()),
+                ]),
+            ),
+        ]);
+
+        let text = value.to_base64();
+        assert_eq!(Bencode::from_base64(&text), Ok(value));
+    }
+}
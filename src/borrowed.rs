@@ -0,0 +1,386 @@
+//! Borrowed module contains data structures and procedures
+//! related to viewing bencoded data without copying it.
+use crate::lexer::{SpannedRef, TokenRef};
+use crate::writer::BencodeWriter;
+use crate::{parser, value_spans, Error};
+
+use std::borrow::Cow;
+use std::io::Write;
+use std::{fmt, str};
+
+#[cfg(test)]
+use std::cell::Cell;
+
+#[cfg(test)]
+thread_local! {
+    /// Number of byte strings interpreted as UTF-8 by current thread.
+    static INTERPRETED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Byte string borrowed from the parsed data. Interpretation of its
+/// content as UTF-8 is deferred until it is requested, so fields which
+/// are never accessed cost nothing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LazyBytes<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> LazyBytes<'a> {
+    /// Wraps given slice of bytes.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        LazyBytes { bytes }
+    }
+
+    /// Returns raw content of the byte string.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Interprets content of the byte string as UTF-8. Returns `None`,
+    /// if it is not valid UTF-8.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::LazyBytes;
+    ///
+    /// assert_eq!(LazyBytes::new(b"spam").as_str(), Some("spam"));
+    /// assert_eq!(LazyBytes::new(b"\xff").as_str(), None);
+    /// ```
+    pub fn as_str(&self) -> Option<&'a str> {
+        #[cfg(test)]
+        INTERPRETED.with(|count| count.set(count.get() + 1));
+        str::from_utf8(self.bytes).ok()
+    }
+}
+
+/// Borrowed counterpart of [`Bencode`](crate::Bencode), whose byte strings
+/// refer to the slice of bytes it was parsed from. Dictionary entries
+/// are kept in the order they appeared in the data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BencodeRef<'a> {
+    /// Can be positive or negative.
+    Integer(i64),
+    /// Fixed-length string of bytes.
+    ByteString(LazyBytes<'a>),
+    /// List of bencoded values.
+    List(Vec<BencodeRef<'a>>),
+    /// Entries of associative array, in order of appearance.
+    Dictionary(Vec<(&'a [u8], BencodeRef<'a>)>),
+}
+
+impl<'a> BencodeRef<'a> {
+    /// Parses given slice of bytes into borrowed view. Byte strings are
+    /// neither copied nor interpreted while parsing. Lists and dictionaries
+    /// can be nested at most [`DEFAULT_MAX_DEPTH`](parser::DEFAULT_MAX_DEPTH)
+    /// levels deep.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::{BencodeRef, LazyBytes};
+    ///
+    /// let left = BencodeRef::parse(b"l4:spami42ee").unwrap();
+    /// let right = BencodeRef::List(vec![
+    ///     BencodeRef::ByteString(LazyBytes::new(b"spam")),
+    ///     BencodeRef::Integer(42),
+    /// ]);
+    /// assert_eq!(left, right);
+    /// ```
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let mut spans = value_spans(data)?;
+        spans.reverse();
+        match spans.pop() {
+            Some(spanned) => parse_spanned(spanned, &mut spans, 0).map_err(Error::Parser),
+            None => Err(Error::Parser(parser::Error::NoTokens)),
+        }
+    }
+
+    /// Transforms view into owned vector of canonically bencoded bytes,
+    /// with dictionary keys in sorted order.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = BencodeWriter::new(Vec::new());
+        self.write(&mut writer);
+        writer.into_inner()
+    }
+
+    fn write(&self, writer: &mut BencodeWriter<Vec<u8>>) {
+        // Writing into vector of bytes cannot fail.
+        match self {
+            BencodeRef::Integer(n) => writer.write_integer(*n).unwrap(),
+            BencodeRef::ByteString(s) => writer.write_byte_string(s.as_bytes()).unwrap(),
+            BencodeRef::List(vec) => {
+                writer.begin_list().unwrap();
+                vec.iter().for_each(|elem| elem.write(writer));
+                writer.end_list().unwrap();
+            }
+            BencodeRef::Dictionary(entries) => {
+                let mut sorted = entries.iter().collect::<Vec<&(&[u8], BencodeRef)>>();
+                sorted.sort_by_key(|(key, _)| *key);
+
+                writer.begin_dict().unwrap();
+                for (key, value) in sorted {
+                    writer.write_byte_string(key).unwrap();
+                    value.write(writer);
+                }
+                writer.end_dict().unwrap();
+            }
+        }
+    }
+
+    /// Returns bencoded form of the view. If `original`, the data the view
+    /// was parsed from, is already canonical it is borrowed, otherwise
+    /// canonical encoding is built. Data which is not the encoding of the
+    /// view is never borrowed, even if it is canonical.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::BencodeRef;
+    /// use std::borrow::Cow;
+    ///
+    /// let data = b"d1:bi1e1:ai2ee";
+    /// let view = BencodeRef::parse(data).unwrap();
+    /// assert_eq!(view.encoded_cow(data), Cow::<[u8]>::Owned(b"d1:ai2e1:bi1ee".to_vec()));
+    /// ```
+    pub fn encoded_cow(&self, original: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.has_sorted_keys() && self.canonical_prefix_len(original) == Some(original.len()) {
+            Cow::Borrowed(original)
+        } else {
+            Cow::Owned(self.to_bytes())
+        }
+    }
+
+    /// Returns length of the canonical encoding of the view, with entries
+    /// of dictionaries in their current order, if given slice of bytes
+    /// starts with it. Nothing is allocated while comparing.
+    fn canonical_prefix_len(&self, data: &[u8]) -> Option<usize> {
+        match self {
+            BencodeRef::Integer(n) => {
+                if data.first() != Some(&b'i') {
+                    return None;
+                }
+                let digits = decimal_prefix_len(*n, &data[1..])?;
+                match data.get(1 + digits) {
+                    Some(b'e') => Some(digits + 2),
+                    _ => None,
+                }
+            }
+            BencodeRef::ByteString(s) => string_prefix_len(s.as_bytes(), data),
+            BencodeRef::List(vec) => {
+                let mut index = 1;
+                if data.first() != Some(&b'l') {
+                    return None;
+                }
+                for elem in vec {
+                    index += elem.canonical_prefix_len(&data[index..])?;
+                }
+                match data.get(index) {
+                    Some(b'e') => Some(index + 1),
+                    _ => None,
+                }
+            }
+            BencodeRef::Dictionary(entries) => {
+                let mut index = 1;
+                if data.first() != Some(&b'd') {
+                    return None;
+                }
+                for (key, value) in entries {
+                    index += string_prefix_len(key, &data[index..])?;
+                    index += value.canonical_prefix_len(&data[index..])?;
+                }
+                match data.get(index) {
+                    Some(b'e') => Some(index + 1),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn has_sorted_keys(&self) -> bool {
+        match self {
+            BencodeRef::Integer(_) | BencodeRef::ByteString(_) => true,
+            BencodeRef::List(vec) => vec.iter().all(|elem| elem.has_sorted_keys()),
+            BencodeRef::Dictionary(entries) => {
+                entries.windows(2).all(|pair| pair[0].0 < pair[1].0)
+                    && entries.iter().all(|(_, value)| value.has_sorted_keys())
+            }
+        }
+    }
+}
+
+/// Returns length of the encoding of given byte string, if given slice
+/// of bytes starts with it.
+fn string_prefix_len(string: &[u8], data: &[u8]) -> Option<usize> {
+    let digits = decimal_prefix_len(string.len(), data)?;
+    match data.get(digits..) {
+        Some([b':', rest @ ..]) if rest.starts_with(string) => Some(digits + 1 + string.len()),
+        _ => None,
+    }
+}
+
+/// Returns length of decimal representation of given number, if given
+/// slice of bytes starts with it.
+fn decimal_prefix_len<T: fmt::Display>(number: T, data: &[u8]) -> Option<usize> {
+    // Neither `i64` nor `usize` takes more than 20 bytes.
+    const MAX_LEN: usize = 20;
+    let mut buf = [0; MAX_LEN];
+    let len = {
+        let mut rest = &mut buf[..];
+        write!(rest, "{}", number).ok()?;
+        MAX_LEN - rest.len()
+    };
+    if data.starts_with(&buf[..len]) {
+        Some(len)
+    } else {
+        None
+    }
+}
+
+/// Parses value starting with given token, nested in `depth` containers.
+fn parse_spanned<'a>(
+    spanned: SpannedRef<'a>,
+    spans: &mut Vec<SpannedRef<'a>>,
+    depth: usize,
+) -> Result<BencodeRef<'a>, parser::Error> {
+    match spanned.1 {
+        TokenRef::Dictionary | TokenRef::List if depth >= parser::DEFAULT_MAX_DEPTH => {
+            Err(parser::Error::DepthLimitExceeded)
+        }
+        TokenRef::Dictionary => parse_dict(spans, depth + 1),
+        TokenRef::List => parse_list(spans, depth + 1),
+        TokenRef::Integer(val) => Ok(BencodeRef::Integer(val)),
+        TokenRef::ByteString(bytes) => Ok(BencodeRef::ByteString(LazyBytes::new(bytes))),
+        TokenRef::End => Err(parser::Error::InvalidEndToken),
+    }
+}
+
+fn parse_list<'a>(
+    spans: &mut Vec<SpannedRef<'a>>,
+    depth: usize,
+) -> Result<BencodeRef<'a>, parser::Error> {
+    let mut list = Vec::new();
+    loop {
+        match spans.pop() {
+            Some((_, TokenRef::End)) => break Ok(BencodeRef::List(list)),
+            Some(spanned) => list.push(parse_spanned(spanned, spans, depth)?),
+            None => break Err(parser::Error::NoEndList),
+        }
+    }
+}
+
+fn parse_dict<'a>(
+    spans: &mut Vec<SpannedRef<'a>>,
+    depth: usize,
+) -> Result<BencodeRef<'a>, parser::Error> {
+    let mut entries = Vec::new();
+    loop {
+        match spans.pop() {
+            Some((_, TokenRef::ByteString(key))) => {
+                let val = match spans.pop() {
+                    Some(spanned) => parse_spanned(spanned, spans, depth)?,
+                    None => break Err(parser::Error::NoEndDictionary),
+                };
+                entries.push((key, val));
+            }
+            Some((_, TokenRef::End)) => break Ok(BencodeRef::Dictionary(entries)),
+            _ => break Err(parser::Error::InvalidDictionaryKey),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument;
+
+    #[test]
+    fn test_parse() {
+        let data = b"d4:infod6:lengthi10ee4:name4:spame";
+        let left = BencodeRef::parse(data).unwrap();
+        let right = BencodeRef::Dictionary(vec![
+            (
+                b"info".as_ref(),
+                BencodeRef::Dictionary(vec![(b"length".as_ref(), BencodeRef::Integer(10))]),
+            ),
+            (
+                b"name".as_ref(),
+                BencodeRef::ByteString(LazyBytes::new(b"spam")),
+            ),
+        ]);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_encoded_cow_borrowed() {
+        let data = b"d3:bar4:spam3:fooli1ei-2eee";
+        let view = BencodeRef::parse(data).unwrap();
+        assert!(matches!(view.encoded_cow(data), Cow::Borrowed(_)));
+        assert_eq!(view.encoded_cow(data).as_ref(), data.as_ref());
+    }
+
+    #[test]
+    fn test_encoded_cow_owned() {
+        let data = b"d3:fooi1e3:bar4:spame";
+        let view = BencodeRef::parse(data).unwrap();
+        let left = view.encoded_cow(data);
+        assert!(matches!(left, Cow::Owned(_)));
+        assert_eq!(left.as_ref(), b"d3:bar4:spam3:fooi1ee".as_ref());
+
+        let data = b"li007ee";
+        let view = BencodeRef::parse(data).unwrap();
+        let left = view.encoded_cow(data);
+        assert!(matches!(left, Cow::Owned(_)));
+        assert_eq!(left.as_ref(), b"li7ee".as_ref());
+
+        // Canonical data of the same length as the view is not its encoding.
+        let view = BencodeRef::parse(b"l4:spami42ee").unwrap();
+        let left = view.encoded_cow(b"l4:eggsi43ee");
+        assert!(matches!(left, Cow::Owned(_)));
+        assert_eq!(left.as_ref(), b"l4:spami42ee".as_ref());
+    }
+
+    #[test]
+    fn test_lazy_bytes_interpreted_on_access() {
+        let data = {
+            let entries = (0..1000)
+                .map(|i| format!("5:key{:02}5:val{:02}", i % 100, i / 10))
+                .collect::<String>();
+            format!("l{}d4:name4:spamee", entries)
+        };
+        let before = INTERPRETED.with(Cell::get);
+        let (view, counts) = instrument::count(|| BencodeRef::parse(data.as_bytes()).unwrap());
+
+        // Neither of the 2002 byte strings is copied or interpreted while
+        // parsing, only vectors of tokens and containers are allocated.
+        assert!(counts.allocations < 10, "{:?}", counts);
+        assert_eq!(INTERPRETED.with(Cell::get), before);
+
+        let name = match &view {
+            BencodeRef::List(list) => match list.last() {
+                Some(BencodeRef::Dictionary(entries)) => match entries.first() {
+                    Some((_, BencodeRef::ByteString(name))) => name.as_str(),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        };
+        assert_eq!(name, Some("spam"));
+        assert_eq!(INTERPRETED.with(Cell::get) - before, 1);
+    }
+
+    #[test]
+    fn test_parse_deeply_nested() {
+        let nested = |depth: usize| format!("{}{}", "l".repeat(depth), "e".repeat(depth));
+
+        let data = nested(parser::DEFAULT_MAX_DEPTH);
+        assert!(BencodeRef::parse(data.as_bytes()).is_ok());
+
+        let data = nested(50_000);
+        assert_eq!(
+            BencodeRef::parse(data.as_bytes()),
+            Err(Error::Parser(parser::Error::DepthLimitExceeded))
+        );
+    }
+}
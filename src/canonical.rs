@@ -0,0 +1,180 @@
+//! Canonical module contains data structures and procedures
+//! related to detecting non-canonical encoding of tokenized input.
+use crate::lexer::{SpannedRef, TokenRef};
+
+use std::fmt;
+use std::ops::Range;
+
+/// Represents recoverable non-canonical constructs, which do not prevent parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// Dictionary key at given offset is not greater than the key preceding it.
+    UnsortedKey { offset: usize, key: String },
+    /// Integer at given offset is written with leading zeros.
+    LeadingZero { offset: usize },
+    /// Integer at given offset is written as negative zero.
+    NegativeZero { offset: usize },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnsortedKey { offset, key } => write!(
+                f,
+                "Dictionary key \"{}\" at byte {} is out of sorted order.",
+                key, offset
+            ),
+            Warning::LeadingZero { offset } => {
+                write!(f, "Integer at byte {} has leading zeros.", offset)
+            }
+            Warning::NegativeZero { offset } => {
+                write!(f, "Integer at byte {} is a negative zero.", offset)
+            }
+        }
+    }
+}
+
+impl Warning {
+    /// Returns offset of the byte at which the construct starts.
+    pub fn offset(&self) -> usize {
+        match self {
+            Warning::UnsortedKey { offset, .. }
+            | Warning::LeadingZero { offset }
+            | Warning::NegativeZero { offset } => *offset,
+        }
+    }
+}
+
+/// State of the container that the tokens currently belong to.
+enum Frame<'a> {
+    List,
+    Dictionary {
+        last_key: Option<&'a [u8]>,
+        expects_key: bool,
+    },
+}
+
+/// Marks that value is about to be read from current container.
+fn begin_value(stack: &mut [Frame<'_>]) {
+    if let Some(Frame::Dictionary { expects_key, .. }) = stack.last_mut() {
+        *expects_key = true;
+    }
+}
+
+/// Checks integer at given range of bytes for non-canonical encoding.
+pub(crate) fn check_integer(data: &[u8], span: &Range<usize>) -> Option<Warning> {
+    let body = &data[span.start + 1..span.end - 1];
+    let digits = body.strip_prefix(b"-").unwrap_or(body);
+    match digits {
+        b"0" if body.len() != digits.len() => Some(Warning::NegativeZero { offset: span.start }),
+        [b'0', _, ..] => Some(Warning::LeadingZero { offset: span.start }),
+        _ => None,
+    }
+}
+
+/// Returns all non-canonical constructs found in the tokens
+/// paired with ranges of `data` they were read from.
+pub(crate) fn check(data: &[u8], spans: &[SpannedRef<'_>]) -> Vec<Warning> {
+    let mut warnings = unsorted_keys(spans)
+        .map(|(span, key)| Warning::UnsortedKey {
+            offset: span.start,
+            key: String::from_utf8_lossy(key).into_owned(),
+        })
+        .collect::<Vec<Warning>>();
+    warnings.extend(
+        spans
+            .iter()
+            .filter(|(_, token)| matches!(token, TokenRef::Integer(_)))
+            .filter_map(|(span, _)| check_integer(data, span)),
+    );
+    warnings.sort_by_key(Warning::offset);
+    warnings
+}
+
+/// Returns spans and contents of dictionary keys which are not greater
+/// than the key preceding them. Tokens are walked lazily, so looking for
+/// the first such key stops right after it.
+pub(crate) fn unsorted_keys<'s, 'a: 's>(
+    spans: &'s [SpannedRef<'a>],
+) -> impl Iterator<Item = (&'s Range<usize>, &'a [u8])> + 's {
+    let mut stack = Vec::new();
+    spans.iter().filter_map(move |(span, token)| {
+        match token {
+            TokenRef::Dictionary => {
+                begin_value(&mut stack);
+                stack.push(Frame::Dictionary {
+                    last_key: None,
+                    expects_key: true,
+                });
+            }
+            TokenRef::List => {
+                begin_value(&mut stack);
+                stack.push(Frame::List);
+            }
+            TokenRef::End => {
+                stack.pop();
+            }
+            TokenRef::Integer(_) => begin_value(&mut stack),
+            TokenRef::ByteString(string) => match stack.last_mut() {
+                Some(Frame::Dictionary {
+                    last_key,
+                    expects_key,
+                }) if *expects_key => {
+                    let unsorted = matches!(last_key, Some(last) if *last >= *string);
+                    *last_key = Some(string);
+                    *expects_key = false;
+                    if unsorted {
+                        return Some((span, *string));
+                    }
+                }
+                _ => begin_value(&mut stack),
+            },
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+
+    fn warnings(data: &[u8]) -> Vec<Warning> {
+        match lexer::parse_spans_ref_recover(data) {
+            (spans, None) => check(data, &spans),
+            (_, Some((_, err))) => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_check_canonical() {
+        assert_eq!(warnings(b"d3:bari0e3:fooli-1ei10eee"), vec![]);
+    }
+
+    #[test]
+    fn test_check_unsorted_keys() {
+        let left = warnings(b"d3:fooi1e3:bard1:bi0e1:ai0eee");
+        let right = vec![
+            Warning::UnsortedKey {
+                offset: 9,
+                key: "bar".into(),
+            },
+            Warning::UnsortedKey {
+                offset: 21,
+                key: "a".into(),
+            },
+        ];
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_check_integers() {
+        let left = warnings(b"li03ei-0ei-01ee");
+        let right = vec![
+            Warning::LeadingZero { offset: 1 },
+            Warning::NegativeZero { offset: 5 },
+            Warning::LeadingZero { offset: 9 },
+        ];
+        assert_eq!(left, right);
+    }
+}
@@ -0,0 +1,53 @@
+//! Instrument module contains test-only global allocator,
+//! which counts allocations made by the current thread.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static REALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn increment(counter: &'static std::thread::LocalKey<Cell<usize>>) {
+    // Counters are unavailable while the thread is being torn down.
+    let _ = counter.try_with(|count| count.set(count.get() + 1));
+}
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        increment(&ALLOCATIONS);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        increment(&REALLOCATIONS);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Numbers of allocator calls made while running instrumented code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Counts {
+    pub allocations: usize,
+    pub reallocations: usize,
+}
+
+/// Runs given function and counts allocator calls it made.
+pub(crate) fn count<T>(f: impl FnOnce() -> T) -> (T, Counts) {
+    let before = (ALLOCATIONS.with(Cell::get), REALLOCATIONS.with(Cell::get));
+    let res = f();
+    let counts = Counts {
+        allocations: ALLOCATIONS.with(Cell::get) - before.0,
+        reallocations: REALLOCATIONS.with(Cell::get) - before.1,
+    };
+    (res, counts)
+}
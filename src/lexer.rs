@@ -1,13 +1,16 @@
 //! Lexer module contains data structures and procedures
 //! related to parsing raw input into tokens.
 use std::cmp::PartialEq;
-use std::{error, fmt};
+use std::ops::Range;
+use std::{error, fmt, str};
 
 /// Represents possible complications that can occur during parsing raw data.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     /// Non-numeric character provided between `i` and `e` tokens.
     ReadInt,
+    /// No characters provided between `i` and `e` tokens.
+    EmptyInteger,
     /// Non numeric character provided as length of list.
     ReadLen,
     /// Parsing string of bytes failed.
@@ -16,6 +19,10 @@ pub enum Error {
     ReadFirstByte(char),
     /// Empty slice provided.
     EmptySlice,
+    /// Declared length of byte string exceeds `isize::MAX`.
+    LengthTooLarge(usize),
+    /// Data ends before the token, described by `context`, is complete.
+    UnexpectedEof { context: &'static str },
 }
 
 impl error::Error for Error {}
@@ -24,6 +31,7 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::ReadInt => write!(f, "Integers can only be composed of numeric characters."),
+            Error::EmptyInteger => write!(f, "Integers must have at least one digit."),
             Error::ReadLen => write!(f, "Length can only be composed of numberic characters."),
             Error::ReadByteString => write!(f, "The data contains a malformed string of bytes."),
             Error::ReadFirstByte(c) => {
@@ -34,98 +42,291 @@ impl fmt::Display for Error {
                 f.write_str(msg.as_str())
             }
             Error::EmptySlice => write!(f, "Given slice is empty."),
+            Error::LengthTooLarge(len) => write!(
+                f,
+                "Declared length of byte string ({}) exceeds the maximum of {}.",
+                len,
+                isize::MAX
+            ),
+            Error::UnexpectedEof { context } => {
+                write!(f, "Data ends before the {} is complete.", context)
+            }
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum Token {
+pub enum Token {
+    /// Beginning of the dictionary.
     Dictionary,
+    /// Beginning of the list.
     List,
+    /// Complete integer, along with its value.
     Integer(i64),
-    ByteString(String),
+    /// Complete string of bytes, without its length.
+    ByteString(Vec<u8>),
+    /// End of the most recently started list or dictionary.
     End,
 }
 
-const INTEGER_START: &'static [u8] = b"i";
-const INTEGER_END: &'static [u8] = b"e";
+/// Token paired with range of bytes it occupies in the input.
+pub type Spanned = (Range<usize>, Token);
 
-impl Token {
-    fn shift(&self) -> usize {
-        match self {
-            Token::Dictionary | Token::List | Token::End => 1, // single characters
-            Token::Integer(num) => INTEGER_START.len() + str_len(num) + INTEGER_END.len(),
+/// Counterpart of `Token`, whose byte string borrows its content
+/// from the input instead of copying it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TokenRef<'a> {
+    Dictionary,
+    List,
+    Integer(i64),
+    ByteString(&'a [u8]),
+    End,
+}
 
-            // size of bytes that contains length information + size of string + size of ":"
-            Token::ByteString(string) => {
-                let size = string.as_bytes().len();
-                str_len(size) + size + b":".len()
-            }
+impl<'a> TokenRef<'a> {
+    /// Copies content of the token into owned `Token`.
+    pub(crate) fn to_token(self) -> Token {
+        match self {
+            TokenRef::Dictionary => Token::Dictionary,
+            TokenRef::List => Token::List,
+            TokenRef::Integer(n) => Token::Integer(n),
+            TokenRef::ByteString(bytes) => Token::ByteString(bytes.to_vec()),
+            TokenRef::End => Token::End,
         }
     }
 }
 
+/// Borrowed token paired with range of bytes it occupies in the input.
+pub(crate) type SpannedRef<'a> = (Range<usize>, TokenRef<'a>);
+
+/// Borrowed tokens read before the first complication, along with
+/// the error and offset of the byte at which it occurred, if any.
+pub(crate) type RecoveredRef<'a> = (Vec<SpannedRef<'a>>, Option<(usize, Error)>);
+
+/// Parses given slice of bytes into tokens, each paired with range of bytes
+/// it occupies in the slice. Unlike parsing into `Bencode`, the structure
+/// of tokens is not validated, so the slice may contain many values.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::lexer::{tokens, Token};
+///
+/// let left = tokens(b"li42ee").unwrap();
+/// let right = vec![(0..1, Token::List), (1..5, Token::Integer(42)), (5..6, Token::End)];
+/// assert_eq!(left, right);
+/// ```
+pub fn tokens(data: &[u8]) -> Result<Vec<Spanned>, Error> {
+    parse_spans(data)
+}
+
 /// Parses given slice of bytes into token vector.
+#[cfg(test)]
 pub(crate) fn parse(slice: &[u8]) -> Result<Vec<Token>, Error> {
+    match parse_recover(slice) {
+        (tokens, None) => Ok(tokens),
+        (_, Some((_, err))) => Err(err),
+    }
+}
+
+/// Parses given slice of bytes into token vector, stopping at the first
+/// complication. Returns tokens read before it, along with the error
+/// and offset of the byte at which it occurred.
+pub(crate) fn parse_recover(slice: &[u8]) -> (Vec<Token>, Option<(usize, Error)>) {
+    let (spans, err) = parse_spans_recover(slice);
+    (spans.into_iter().map(|(_, token)| token).collect(), err)
+}
+
+/// Parses given slice of bytes into token vector, where every token is
+/// paired with range of bytes it occupies in the slice.
+pub(crate) fn parse_spans(slice: &[u8]) -> Result<Vec<Spanned>, Error> {
+    match parse_spans_recover(slice) {
+        (spans, None) => Ok(spans),
+        (_, Some((_, err))) => Err(err),
+    }
+}
+
+/// Parses given slice of bytes like `parse_recover` does, but
+/// pairs every token with range of bytes it occupies in the slice.
+pub(crate) fn parse_spans_recover(slice: &[u8]) -> (Vec<Spanned>, Option<(usize, Error)>) {
+    let (spans, err) = parse_spans_ref_recover(slice);
+    let spans = spans
+        .into_iter()
+        .map(|(span, token)| (span, token.to_token()))
+        .collect();
+    (spans, err)
+}
+
+/// Parses given slice of bytes like `parse_spans_recover` does, but
+/// without copying content of byte strings out of the slice.
+pub(crate) fn parse_spans_ref_recover(slice: &[u8]) -> RecoveredRef<'_> {
     let mut index = 0;
     let mut ret = Vec::new();
     loop {
-        match tokenize(&slice[index..]) {
-            Ok(token) => {
-                index += token.clone().shift();
-                ret.push(token);
+        match tokenize_ref(&slice[index..]) {
+            Ok((token, len)) => {
+                ret.push((index..index + len, token));
+                index += len;
             }
-            Err(Error::EmptySlice) => break Ok(ret),
+            Err(Error::EmptySlice) => break (ret, None),
+            Err(err) => break (ret, Some((index, err))),
+        }
+    }
+}
+
+/// Confirms that given spans cover the slice contiguously, one after
+/// another, and that every span holds exactly the bytes of its token.
+/// Returns start of the first span that does not, on failure.
+pub(crate) fn check_spans(slice: &[u8], spans: &[SpannedRef<'_>]) -> Result<(), usize> {
+    let mut expected_start = 0;
+    for (span, token) in spans {
+        if span.start != expected_start || !span_matches(slice, span, token) {
+            return Err(span.start);
+        }
+        expected_start = span.end;
+    }
+    Ok(())
+}
+
+fn span_matches(slice: &[u8], span: &Range<usize>, token: &TokenRef<'_>) -> bool {
+    let bytes = match slice.get(span.clone()) {
+        Some(bytes) => bytes,
+        None => return false,
+    };
+    match token {
+        TokenRef::Dictionary => bytes == [DICTIONARY_BYTE as u8],
+        TokenRef::List => bytes == [LIST_BYTE as u8],
+        TokenRef::End => bytes == [END_BYTE as u8],
+        TokenRef::Integer(_) => {
+            bytes.len() > INTEGER_START.len() + INTEGER_END.len()
+                && bytes.starts_with(INTEGER_START)
+                && bytes.ends_with(INTEGER_END)
+        }
+        TokenRef::ByteString(string) => match bytes.len().checked_sub(string.len() + 1) {
+            Some(digits) if digits > 0 => {
+                bytes[..digits].iter().all(u8::is_ascii_digit)
+                    && bytes[digits] == STRING_DELIMETER[0]
+                    && &bytes[digits + 1..] == *string
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Parses given slice of bytes like `parse_spans_ref_recover` does, unless
+/// it contains more than `max_tokens` tokens, in which case `None` is
+/// returned as soon as the limit is exceeded.
+pub(crate) fn parse_spans_ref_bounded(slice: &[u8], max_tokens: usize) -> Option<RecoveredRef<'_>> {
+    let mut index = 0;
+    let mut ret = Vec::new();
+    loop {
+        match tokenize_ref(&slice[index..]) {
+            Ok(_) if ret.len() == max_tokens => break None,
+            Ok((token, len)) => {
+                ret.push((index..index + len, token));
+                index += len;
+            }
+            Err(Error::EmptySlice) => break Some((ret, None)),
+            Err(err) => break Some((ret, Some((index, err)))),
+        }
+    }
+}
+
+/// Counts tokens in given slice of bytes, without collecting them into vector.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::lexer::count_tokens;
+///
+/// assert_eq!(count_tokens(b"l4:spami42ee"), Ok(4));
+/// ```
+pub fn count_tokens(slice: &[u8]) -> Result<usize, Error> {
+    let mut index = 0;
+    let mut count = 0;
+    loop {
+        match tokenize_ref(&slice[index..]) {
+            Ok((_, len)) => {
+                index += len;
+                count += 1;
+            }
+            Err(Error::EmptySlice) => break Ok(count),
             Err(err) => break Err(err),
         }
     }
 }
 
-/// Parses given slice of bytes into vector of bytes,
-/// till found given end character.
-fn read_until(slice: &[u8], end: char) -> Vec<u8> {
-    slice
-        .into_iter()
-        .take_while(|&c| *c as char != end)
-        .cloned()
-        .collect()
+/// Returns prefix of given slice of bytes, till found given end character.
+fn read_until(slice: &[u8], end: char) -> &[u8] {
+    let len = slice.iter().take_while(|&c| *c as char != end).count();
+    &slice[..len]
 }
 
-/// Parses given slice of bytes into integer.
-fn read_int(slice: &[u8]) -> Result<i64, Error> {
-    read_until(slice, 'e')
-        .into_iter()
-        .map(|c| c as char)
-        .collect::<String>()
-        .parse()
-        .map_err(|_| Error::ReadInt)
+/// Parses given ASCII characters into number, without allocating.
+fn parse_ascii<T: str::FromStr>(bytes: &[u8]) -> Option<T> {
+    str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Parses given slice of bytes into integer. Returns it along with
+/// number of bytes it occupies in the slice.
+fn read_int(slice: &[u8]) -> Result<(i64, usize), Error> {
+    let body = read_until(slice, 'e');
+    let len = body.len();
+    // Only single optional minus sign followed by ASCII digits is
+    // allowed, which rules out plus signs and whitespace.
+    let digits = body.strip_prefix(b"-").unwrap_or(body);
+    let well_formed = digits.iter().all(u8::is_ascii_digit);
+    if len == slice.len() {
+        // Data ending inside well-formed digits could have been truncated.
+        return if well_formed {
+            Err(Error::UnexpectedEof { context: "integer" })
+        } else {
+            Err(Error::ReadInt)
+        };
+    }
+    if body.is_empty() {
+        return Err(Error::EmptyInteger);
+    }
+    if !well_formed {
+        return Err(Error::ReadInt);
+    }
+    parse_ascii(body)
+        .map(|num| (num, len))
+        .ok_or(Error::ReadInt)
 }
 
 /// Parses given slice of bytes into integer, which represents
 /// length of byte string.
 fn read_len(slice: &[u8]) -> Result<usize, Error> {
-    read_until(slice, ':')
-        .into_iter()
-        .map(|c| c as char)
-        .collect::<String>()
-        .parse()
-        .map_err(|_| Error::ReadLen)
-}
-
-/// Returns length of string generated by any data structure,
-/// which implements Display trait.
-fn str_len(d: impl std::fmt::Display) -> usize {
-    format!("{}", d).chars().count()
+    parse_ascii(read_until(slice, ':')).ok_or(Error::ReadLen)
 }
 
-const STRING_DELIMETER: &'static [u8] = b":";
+const STRING_DELIMETER: &[u8] = b":";
 
-fn read_byte_string(slice: &[u8]) -> Result<String, Error> {
+/// Parses given slice of bytes into string of bytes. Returns it along
+/// with number of bytes it occupies in the slice, including its length.
+fn read_byte_string(slice: &[u8]) -> Result<(&[u8], usize), Error> {
+    let prefix = read_until(slice, ':');
+    if prefix.len() == slice.len() {
+        return if prefix.iter().all(u8::is_ascii_digit) {
+            Err(Error::UnexpectedEof {
+                context: "byte string length",
+            })
+        } else {
+            Err(Error::ReadByteString)
+        };
+    }
     let size = read_len(slice).map_err(|_| Error::ReadByteString)?;
-    let shift = str_len(size) + STRING_DELIMETER.len();
-    let shifted_slice = &slice[shift..shift + size];
+    if size > isize::MAX as usize {
+        return Err(Error::LengthTooLarge(size));
+    }
+    let shift = prefix.len() + STRING_DELIMETER.len();
+    let end = shift.checked_add(size).ok_or(Error::LengthTooLarge(size))?;
+    let shifted_slice = slice.get(shift..end).ok_or(Error::UnexpectedEof {
+        context: "byte string",
+    })?;
 
-    Ok(shifted_slice.into_iter().map(|&c| c as char).collect())
+    Ok((shifted_slice, end))
 }
 
 const DICTIONARY_BYTE: char = 'd';
@@ -135,45 +336,110 @@ const INTEGER_BYTE: char = 'i';
 const SLICE_RANGE_START: char = '0';
 const SLICE_RANGE_END: char = '9';
 
-/// Transform given slice of bytes into vector of tokens.
-fn tokenize(slice: &[u8]) -> Result<Token, Error> {
+const INTEGER_START: &[u8] = b"i";
+const INTEGER_END: &[u8] = b"e";
+
+/// Transform given slice of bytes into single token. Returns it along
+/// with number of bytes it occupies in the slice.
+#[cfg(test)]
+fn tokenize(slice: &[u8]) -> Result<(Token, usize), Error> {
+    tokenize_ref(slice).map(|(token, len)| (token.to_token(), len))
+}
+
+/// Transforms given slice of bytes into single token like `tokenize`
+/// does, but borrows content of byte string from the slice.
+fn tokenize_ref(slice: &[u8]) -> Result<(TokenRef<'_>, usize), Error> {
     match slice.first() {
         Some(byte) => match *byte as char {
-            DICTIONARY_BYTE => Ok(Token::Dictionary),
-            LIST_BYTE => Ok(Token::List),
-            END_BYTE => Ok(Token::End),
-            INTEGER_BYTE => read_int(&slice[1..]).map(Token::Integer),
-            SLICE_RANGE_START..=SLICE_RANGE_END => read_byte_string(slice).map(Token::ByteString),
+            // single characters
+            DICTIONARY_BYTE => Ok((TokenRef::Dictionary, 1)),
+            LIST_BYTE => Ok((TokenRef::List, 1)),
+            END_BYTE => Ok((TokenRef::End, 1)),
+            INTEGER_BYTE => read_int(&slice[INTEGER_START.len()..]).map(|(num, len)| {
+                let shift = INTEGER_START.len() + len + INTEGER_END.len();
+                (TokenRef::Integer(num), shift)
+            }),
+            SLICE_RANGE_START..=SLICE_RANGE_END => {
+                read_byte_string(slice).map(|(string, shift)| (TokenRef::ByteString(string), shift))
+            }
             c => Err(Error::ReadFirstByte(c)),
         },
         None => Err(Error::EmptySlice),
     }
 }
 
+/// Cheaply checks whether given slice of bytes may contain bencoded data,
+/// by looking only at its first byte. It does not validate the data.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::lexer::looks_like_bencode;
+///
+/// assert!(looks_like_bencode(b"d3:fooi42ee"));
+/// assert!(!looks_like_bencode(b"{\"foo\": 42}"));
+/// ```
+pub fn looks_like_bencode(slice: &[u8]) -> bool {
+    match slice.first() {
+        Some(byte) => matches!(
+            *byte as char,
+            DICTIONARY_BYTE | LIST_BYTE | INTEGER_BYTE | SLICE_RANGE_START..=SLICE_RANGE_END
+        ),
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_token_shift() {
-        let size = Token::Integer(-666).shift();
+        let (_, size) = tokenize(b"i-666e").unwrap();
         assert_eq!(size, 6);
 
-        let size = Token::Integer(666).shift();
+        let (_, size) = tokenize(b"i666e").unwrap();
         assert_eq!(size, 5);
+
+        let (_, size) = tokenize(b"i0666e").unwrap();
+        assert_eq!(size, 6);
     }
 
     #[test]
-    fn test_str_len() {
-        assert_eq!(str_len(123), 3);
-        assert_eq!(str_len(1234), 4);
-        assert_eq!(str_len(12345), 5);
+    fn test_token_shift_non_ascii() {
+        // Every byte above 0x7f would take two bytes if re-encoded as UTF-8.
+        let bytes = b"12:\x80\x81\xfe\xff\xc3\xa9\xe2\x82\xac\xf0\x9f\x98i42e";
+        let (token, size) = tokenize(bytes).unwrap();
+        assert_eq!(size, 2 + 1 + 12);
+        assert_eq!(token, Token::ByteString(bytes[3..15].to_vec()));
+
+        let (spans, _) = parse_spans_ref_recover(bytes);
+        assert_eq!(spans[1], (15..19, TokenRef::Integer(42)));
+        assert_eq!(check_spans(bytes, &spans), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_spans_ref() {
+        let data = b"d4:spaml1:aee";
+        let (spans, err) = parse_spans_ref_recover(data);
+        assert_eq!(err, None);
+
+        // Content of byte strings is borrowed, not copied.
+        match spans[1].1 {
+            TokenRef::ByteString(bytes) => assert_eq!(bytes.as_ptr(), data[3..].as_ptr()),
+            other => panic!("unexpected token: {:?}", other),
+        }
+        let owned = spans
+            .into_iter()
+            .map(|(span, token)| (span, token.to_token()))
+            .collect::<Vec<Spanned>>();
+        assert_eq!(owned, parse_spans(data).unwrap());
     }
 
     #[test]
     fn test_read_byte_string() {
         let bytes = b"5:abcdefgh";
-        assert_eq!(read_byte_string(bytes), Ok(String::from("abcde")));
+        assert_eq!(read_byte_string(bytes), Ok((b"abcde".as_ref(), 7)));
     }
 
     #[test]
@@ -181,16 +447,37 @@ mod tests {
         let bytes = b"i1234e";
 
         let left = tokenize(bytes).unwrap();
-        let right = Token::Integer(1234);
+        let right = (Token::Integer(1234), 6);
 
         assert_eq!(left, right);
     }
 
+    #[test]
+    fn test_tokenize_int_sign() {
+        assert_eq!(tokenize(b"i-5e"), Ok((Token::Integer(-5), 4)));
+        assert_eq!(tokenize(b"i5e"), Ok((Token::Integer(5), 3)));
+
+        for bytes in &[&b"i+5e"[..], b"i 5e", b"i5 e", b"i--5e", b"i-+5e", b"i\t5e"] {
+            assert_eq!(tokenize(bytes), Err(Error::ReadInt));
+        }
+    }
+
+    #[test]
+    fn test_tokenize_empty_int() {
+        assert_eq!(tokenize(b"ie"), Err(Error::EmptyInteger));
+        assert_eq!(parse(b"li1eiee"), Err(Error::EmptyInteger));
+        assert_eq!(tokenize(b"i e"), Err(Error::ReadInt));
+        assert_eq!(
+            tokenize(b"i"),
+            Err(Error::UnexpectedEof { context: "integer" })
+        );
+    }
+
     #[test]
     fn test_tokenize_byte_string() {
         let bytes = b"6:abcdefgh";
         let left = tokenize(bytes).unwrap();
-        let right = Token::ByteString("abcdef".into());
+        let right = (Token::ByteString("abcdef".into()), 8);
 
         assert_eq!(left, right);
     }
@@ -209,4 +496,145 @@ mod tests {
         ];
         assert_eq!(left, right);
     }
+
+    #[test]
+    fn test_count_tokens() {
+        let bytes = b"d3:bar4:spam3:fool1:ai42eee";
+        assert_eq!(count_tokens(bytes), Ok(parse(bytes).unwrap().len()));
+        assert_eq!(count_tokens(b""), Ok(0));
+        assert_eq!(count_tokens(b"ixe"), Err(Error::ReadInt));
+    }
+
+    #[test]
+    fn test_parse_recover() {
+        let (tokens, err) = parse_recover(b"li1e4:spamixee");
+        let right = vec![
+            Token::List,
+            Token::Integer(1),
+            Token::ByteString("spam".into()),
+        ];
+        assert_eq!(tokens, right);
+        assert_eq!(err, Some((10, Error::ReadInt)));
+    }
+
+    #[test]
+    fn test_parse_spans_ref_bounded() {
+        let bytes = b"li1ei2ee";
+        assert_eq!(
+            parse_spans_ref_bounded(bytes, 4),
+            Some(parse_spans_ref_recover(bytes))
+        );
+        assert_eq!(parse_spans_ref_bounded(bytes, 3), None);
+        assert_eq!(
+            parse_spans_ref_bounded(b"i1ex", 2),
+            Some(parse_spans_ref_recover(b"i1ex"))
+        );
+    }
+
+    #[test]
+    fn test_looks_like_bencode() {
+        assert!(looks_like_bencode(b"i42e"));
+        assert!(looks_like_bencode(b"le"));
+        assert!(looks_like_bencode(b"de"));
+        assert!(looks_like_bencode(b"0:"));
+        assert!(looks_like_bencode(b"9:"));
+        assert!(!looks_like_bencode(b"e"));
+        assert!(!looks_like_bencode(b"<html>"));
+        assert!(!looks_like_bencode(b""));
+    }
+
+    #[test]
+    fn test_read_byte_string_too_large() {
+        let size = isize::MAX as usize + 1;
+        let bytes = format!("{}:abc", size);
+        assert_eq!(
+            read_byte_string(bytes.as_bytes()),
+            Err(Error::LengthTooLarge(size))
+        );
+    }
+
+    #[test]
+    fn test_parse_pathological_length() {
+        let bytes = format!("l{}:abce", usize::MAX);
+        assert_eq!(
+            parse(bytes.as_bytes()),
+            Err(Error::LengthTooLarge(usize::MAX))
+        );
+        assert_eq!(
+            parse(b"99999999999999999999999:abc"),
+            Err(Error::ReadByteString)
+        );
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        let eof = |context| Err(Error::UnexpectedEof { context });
+        assert_eq!(parse(b"li4"), eof("integer"));
+        assert_eq!(parse(b"i42"), eof("integer"));
+        assert_eq!(parse(b"i-"), eof("integer"));
+        assert_eq!(parse(b"l4:sp"), eof("byte string"));
+        assert_eq!(parse(b"l12"), eof("byte string length"));
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        assert_eq!(parse(b"i4x2e"), Err(Error::ReadInt));
+        assert_eq!(parse(b"i4x2"), Err(Error::ReadInt));
+        assert_eq!(parse(b"1x"), Err(Error::ReadByteString));
+        assert_eq!(parse(b"1x:a"), Err(Error::ReadByteString));
+    }
+
+    #[test]
+    fn test_read_byte_string_truncated() {
+        assert_eq!(
+            read_byte_string(b"10:abc"),
+            Err(Error::UnexpectedEof {
+                context: "byte string"
+            })
+        );
+        assert_eq!(read_byte_string(b"0:"), Ok((b"".as_ref(), 2)));
+        assert_eq!(parse(b"0:"), Ok(vec![Token::ByteString(Vec::new())]));
+    }
+
+    #[test]
+    fn test_check_spans() {
+        let slice = b"l4:spami42ee";
+        let (spans, _) = parse_spans_ref_recover(slice);
+        assert_eq!(check_spans(slice, &spans), Ok(()));
+
+        // Byte string span one byte short, as if the length
+        // prefix delimiter was not counted in the shift.
+        let mut shifted = spans.clone();
+        shifted[1].0 = 1..6;
+        shifted[2].0 = 6..10;
+        assert_eq!(check_spans(slice, &shifted), Err(1));
+
+        let mut overlapping = spans;
+        overlapping[2].0 = 6..11;
+        assert_eq!(check_spans(slice, &overlapping), Err(6));
+    }
+
+    #[test]
+    fn test_tokens() {
+        let data = b"d4:infod6:lengthi1024ee4:name4:spame";
+        let left = tokens(data).unwrap();
+        let right = vec![
+            (0..1, Token::Dictionary),
+            (1..7, Token::ByteString(b"info".to_vec())),
+            (7..8, Token::Dictionary),
+            (8..16, Token::ByteString(b"length".to_vec())),
+            (16..22, Token::Integer(1024)),
+            (22..23, Token::End),
+            (23..29, Token::ByteString(b"name".to_vec())),
+            (29..35, Token::ByteString(b"spam".to_vec())),
+            (35..36, Token::End),
+        ];
+        assert_eq!(left, right);
+
+        // Slicing the input with spans gives exact bytes of the tokens.
+        let (info_start, info_end) = (left[2].0.start, left[5].0.end);
+        assert_eq!(&data[info_start..info_end], b"d6:lengthi1024ee".as_ref());
+
+        assert_eq!(tokens(b"i1ex"), Err(Error::ReadFirstByte('x')));
+    }
 }
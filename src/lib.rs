@@ -23,28 +23,122 @@
 //! let left: Bencode = "l3:loli100e4:ruste".try_into().unwrap();
 //! let right = {
 //!     let mut res = Vec::new();
-//!     res.push(Bencode::ByteString(String::from("lol")));
+//!     res.push(Bencode::ByteString(b"lol".to_vec()));
 //!     res.push(Bencode::Integer(100));
-//!     res.push(Bencode::ByteString(String::from("rust")));
+//!     res.push(Bencode::ByteString(b"rust".to_vec()));
 //!     Bencode::List(res)
 //! };
 //! assert_eq!(left, right)
 //! ```
 //! [`TryFrom`]: https://doc.rust-lang.org/std/convert/trait.TryFrom.html
 //! [`TryInto`]: https://doc.rust-lang.org/std/convert/trait.TryInto.html
+pub mod arena;
+mod base64;
+pub mod borrowed;
+pub mod canonical;
+#[cfg(test)]
+mod instrument;
 pub mod lexer;
 pub mod parser;
+pub mod schema;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod stream;
+pub mod torrent;
+pub mod writer;
+
+use crate::lexer::{SpannedRef, TokenRef};
 
+use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::{error, fmt};
+use std::ops::Range;
+use std::{error, fmt, io};
 
-pub use parser::Bencode;
+pub use arena::{ArenaParser, BencodeArena};
+pub use borrowed::{BencodeRef, LazyBytes};
+pub use canonical::Warning;
+pub use lexer::{tokens, Token};
+pub use parser::{Bencode, DictBuilder};
+pub use stream::{parse_many_lenient, StreamDecoder};
+pub use writer::{BencodeWriter, ChunkEncoder};
 
 /// Error wrapper for errors from both lexer and parser modules.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     Parser(parser::Error),
     Lexer(lexer::Error),
+    /// There is no data to parse.
+    EmptyInput,
+    /// Parsing required more steps than allowed.
+    BudgetExhausted,
+    /// Data does not start with the expected prefix.
+    MissingPrefix,
+    /// There are bytes left after the first complete value.
+    TrailingData {
+        offset: usize,
+    },
+    /// Integer at given offset has leading zeros or is a negative zero.
+    NonCanonicalInteger {
+        offset: usize,
+    },
+    /// Dictionary key at given offset is not greater than the key preceding it.
+    UnsortedKeys {
+        offset: usize,
+    },
+    /// Token at given offset does not begin where the previous one ended,
+    /// or its span does not match its content. Lexer is only checked for
+    /// this in debug builds.
+    OverlappingTokens {
+        offset: usize,
+    },
+    /// Character at given offset of base64 string cannot be decoded.
+    InvalidBase64 {
+        offset: usize,
+    },
+    /// Value being decoded from a stream takes more bytes than given limit.
+    ValueTooLong {
+        limit: usize,
+    },
+    /// Reading data failed.
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
+}
+
+impl Error {
+    /// Moves offset carried by the error by given number of bytes, for
+    /// errors found in data which does not start at the beginning of the
+    /// caller's buffer. Lexer errors carry no offsets and are left as is.
+    fn shifted(self, by: usize) -> Self {
+        match self {
+            Error::TrailingData { offset } => Error::TrailingData {
+                offset: offset + by,
+            },
+            Error::NonCanonicalInteger { offset } => Error::NonCanonicalInteger {
+                offset: offset + by,
+            },
+            Error::UnsortedKeys { offset } => Error::UnsortedKeys {
+                offset: offset + by,
+            },
+            Error::OverlappingTokens { offset } => Error::OverlappingTokens {
+                offset: offset + by,
+            },
+            Error::InvalidBase64 { offset } => Error::InvalidBase64 {
+                offset: offset + by,
+            },
+            err => err,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
 }
 
 impl error::Error for Error {
@@ -52,6 +146,16 @@ impl error::Error for Error {
         match self {
             Error::Parser(err) => Some(err),
             Error::Lexer(err) => Some(err),
+            Error::EmptyInput
+            | Error::BudgetExhausted
+            | Error::MissingPrefix
+            | Error::TrailingData { .. }
+            | Error::NonCanonicalInteger { .. }
+            | Error::UnsortedKeys { .. }
+            | Error::OverlappingTokens { .. }
+            | Error::InvalidBase64 { .. }
+            | Error::ValueTooLong { .. }
+            | Error::Io { .. } => None,
         }
     }
 }
@@ -67,13 +171,164 @@ impl fmt::Display for Error {
                 write!(f, "Lexer Error: ")?;
                 err.fmt(f)
             }
+            Error::EmptyInput => write!(f, "There is no data to parse, the input is empty."),
+            Error::BudgetExhausted => write!(f, "Parsing exceeded the given budget of steps."),
+            Error::MissingPrefix => write!(f, "Data does not start with the expected prefix."),
+            Error::TrailingData { offset } => write!(
+                f,
+                "There is unexpected data after the value, starting at byte {}.",
+                offset
+            ),
+            Error::NonCanonicalInteger { offset } => {
+                write!(f, "Integer at byte {} is not encoded canonically.", offset)
+            }
+            Error::UnsortedKeys { offset } => write!(
+                f,
+                "Dictionary key at byte {} is out of sorted order.",
+                offset
+            ),
+            Error::OverlappingTokens { offset } => write!(
+                f,
+                "Token at byte {} does not follow the previous one contiguously.",
+                offset
+            ),
+            Error::InvalidBase64 { offset } => {
+                write!(f, "Character at byte {} is not valid base64.", offset)
+            }
+            Error::ValueTooLong { limit } => {
+                write!(f, "Value takes more than the limit of {} bytes.", limit)
+            }
+            Error::Io { message, .. } => write!(f, "Reading data failed: {}.", message),
         }
     }
 }
 
+/// Parses given slice of bytes, which has to contain exactly one value
+/// without any data following it. Integers with leading zeros or
+/// negative zeros are rejected.
 fn from_bytes(data: &[u8]) -> Result<Bencode, Error> {
-    let tokens = lexer::parse(data).map_err(Error::Lexer)?;
-    parser::parse(tokens).map_err(Error::Parser)
+    from_bytes_with_depth(data, parser::DEFAULT_MAX_DEPTH)
+}
+
+/// Parses given slice of bytes like `from_bytes` does, but with
+/// given maximum nesting depth of lists and dictionaries.
+fn from_bytes_with_depth(data: &[u8], max_depth: usize) -> Result<Bencode, Error> {
+    from_bytes_with_depth_stats(data, max_depth).map(|(value, _)| value)
+}
+
+/// Parses given slice of bytes like `from_bytes_with_depth` does, and
+/// returns metrics collected by the parser along with the value.
+fn from_bytes_with_depth_stats(
+    data: &[u8],
+    max_depth: usize,
+) -> Result<(Bencode, ParseStats), Error> {
+    let spans = value_spans(data)?;
+    from_value_spans(data, &spans, max_depth)
+}
+
+/// Builds value from given spans returned by `value_spans`, validating
+/// it like `from_bytes_with_depth_stats` does.
+fn from_value_spans(
+    data: &[u8],
+    spans: &[SpannedRef<'_>],
+    max_depth: usize,
+) -> Result<(Bencode, ParseStats), Error> {
+    // Spans come from the lexer itself, so this only guards
+    // against regressions in it and is skipped in release builds.
+    if cfg!(debug_assertions) {
+        lexer::check_spans(data, spans).map_err(|offset| Error::OverlappingTokens { offset })?;
+    }
+    let (value, _, stats) =
+        parser::parse_first_with_depth(owned_tokens(spans), max_depth).map_err(Error::Parser)?;
+    check_integers(data, spans)?;
+    Ok((value, stats))
+}
+
+/// Fails with `NonCanonicalInteger` if any of given spans holds integer
+/// which is not in canonical form.
+fn check_integers(data: &[u8], spans: &[SpannedRef<'_>]) -> Result<(), Error> {
+    let non_canonical = spans
+        .iter()
+        .filter(|(_, token)| matches!(token, TokenRef::Integer(_)))
+        .find_map(|(span, _)| canonical::check_integer(data, span));
+    match non_canonical {
+        Some(warning) => Err(Error::NonCanonicalInteger {
+            offset: warning.offset(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Tokenizes given slice of bytes, which has to contain exactly one value
+/// without any data following it. Fails with `EmptyInput` if there is no
+/// data, with lexer error if it prevents the value from being complete,
+/// and with `TrailingData` if anything is left after the value. Structure
+/// of the tokens is left for the parser to validate.
+pub(crate) fn value_spans(data: &[u8]) -> Result<Vec<SpannedRef<'_>>, Error> {
+    if data.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    let (spans, lexer_err) = lexer::parse_spans_ref_recover(data);
+    first_value_spans(data, spans, lexer_err)
+}
+
+/// Validates tokens read from given slice of bytes like `value_spans`
+/// does, given the lexer error which stopped reading them, if any.
+fn first_value_spans<'a>(
+    data: &[u8],
+    spans: Vec<SpannedRef<'a>>,
+    lexer_err: Option<(usize, lexer::Error)>,
+) -> Result<Vec<SpannedRef<'a>>, Error> {
+    match (value_token_count(&spans), lexer_err) {
+        (Some(count), _) => match spans[count - 1].0.end {
+            end if end < data.len() => Err(Error::TrailingData { offset: end }),
+            _ => Ok(spans),
+        },
+        (None, Some((_, err))) => Err(Error::Lexer(err)),
+        (None, None) => Ok(spans),
+    }
+}
+
+/// Returns number of tokens making up the first value, or `None` if the
+/// tokens end before it is complete or it starts with an end token.
+fn value_token_count(spans: &[SpannedRef<'_>]) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, (_, token)) in spans.iter().enumerate() {
+        match token {
+            TokenRef::Dictionary | TokenRef::List => depth += 1,
+            TokenRef::End => depth = depth.checked_sub(1)?,
+            TokenRef::Integer(_) | TokenRef::ByteString(_) => {}
+        }
+        if depth == 0 {
+            return Some(index + 1);
+        }
+    }
+    None
+}
+
+/// Copies given borrowed tokens into owned ones, which the parser builds from.
+fn owned_tokens(spans: &[SpannedRef<'_>]) -> Vec<Token> {
+    spans.iter().map(|(_, token)| token.to_token()).collect()
+}
+
+/// Parses value following given prefix, for example magic bytes of custom
+/// container format. Fails if data does not start with the prefix,
+/// or if there is anything left after the value. Offsets carried by
+/// errors count from the beginning of the data, including the prefix.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{parse_after_prefix, Bencode, Error};
+///
+/// assert_eq!(parse_after_prefix(b"BENCi1e", b"BENC"), Ok(Bencode::Integer(1)));
+/// assert_eq!(parse_after_prefix(b"i1e", b"BENC"), Err(Error::MissingPrefix));
+/// ```
+pub fn parse_after_prefix(data: &[u8], prefix: &[u8]) -> Result<Bencode, Error> {
+    if !data.starts_with(prefix) {
+        return Err(Error::MissingPrefix);
+    }
+    from_bytes(&data[prefix.len()..]).map_err(|err| err.shifted(prefix.len()))
 }
 
 impl<'a> TryFrom<&'a [u8]> for Bencode {
@@ -84,6 +339,463 @@ impl<'a> TryFrom<&'a [u8]> for Bencode {
     }
 }
 
+/// Parses given slice of bytes like `from_bytes` does, taking at most
+/// `max_steps` steps, which bounds the time spent on adversarial input.
+/// Reading a single token and putting a single value into the resulting
+/// tree each count as one step.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{parse_with_budget, Bencode, Error};
+///
+/// assert_eq!(parse_with_budget(b"li1ee", 5), Ok(Bencode::List(vec![Bencode::Integer(1)])));
+/// assert_eq!(parse_with_budget(b"li1ee", 4), Err(Error::BudgetExhausted));
+/// ```
+pub fn parse_with_budget(data: &[u8], max_steps: usize) -> Result<Bencode, Error> {
+    if data.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    let (spans, lexer_err) =
+        lexer::parse_spans_ref_bounded(data, max_steps).ok_or(Error::BudgetExhausted)?;
+    let spans = first_value_spans(data, spans, lexer_err)?;
+    // Whatever the lexer has not used is left for the parser.
+    let (value, _, _) = parser::parse_first_bounded(
+        owned_tokens(&spans),
+        parser::DEFAULT_MAX_DEPTH,
+        max_steps - spans.len(),
+    )
+    .map_err(Error::Parser)?
+    .ok_or(Error::BudgetExhausted)?;
+    check_integers(data, &spans)?;
+    Ok(value)
+}
+
+/// Checks whether two slices of bytes encode the same value, by comparing
+/// their canonical encodings. Fails if any of them cannot be parsed.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::encodings_equal;
+///
+/// assert_eq!(encodings_equal(b"d1:bi2e1:ai1ee", b"d1:ai1e1:bi2ee"), Ok(true));
+/// assert_eq!(encodings_equal(b"i1e", b"i2e"), Ok(false));
+/// ```
+pub fn encodings_equal(a: &[u8], b: &[u8]) -> Result<bool, Error> {
+    let a = from_bytes(a)?;
+    let b = from_bytes(b)?;
+    Ok(a.into_bytes() == b.into_bytes())
+}
+
+/// Checks whether two slices of bytes encode the same value, but differ
+/// in order of dictionary keys, and in nothing else. Fails if any of them
+/// cannot be parsed.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::differ_only_in_order;
+///
+/// assert_eq!(differ_only_in_order(b"d1:bi2e1:ai1ee", b"d1:ai1e1:bi2ee"), Ok(true));
+/// assert_eq!(differ_only_in_order(b"d1:ai1ee", b"d1:ai1ee"), Ok(false));
+/// ```
+pub fn differ_only_in_order(a: &[u8], b: &[u8]) -> Result<bool, Error> {
+    let value = from_bytes(a)?;
+    if value != from_bytes(b)? {
+        return Ok(false);
+    }
+    // Integers are canonical already, so only length prefixes with
+    // leading zeros can make the data longer than its canonical form.
+    let len = value.encoded_len();
+    Ok(a != b && a.len() == len && b.len() == len)
+}
+
+/// Maps each top-level dictionary key to the range of bytes its value
+/// occupies in given slice, which allows editing single values without
+/// re-encoding the whole data. Returns an empty map if the data is valid,
+/// but its top-level value is not a dictionary.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::key_value_ranges;
+///
+/// let data = b"d3:fooi42ee";
+/// let ranges = key_value_ranges(data).unwrap();
+/// assert_eq!(&data[ranges["foo"].clone()], b"i42e");
+/// ```
+pub fn key_value_ranges(data: &[u8]) -> Result<HashMap<String, Range<usize>>, Error> {
+    let spans = value_spans(data)?;
+    parser::parse(owned_tokens(&spans)).map_err(Error::Parser)?;
+
+    let mut ranges = HashMap::new();
+    if let Some((_, TokenRef::Dictionary)) = spans.first() {
+        let mut entries = spans[1..].iter();
+        while let Some((_, TokenRef::ByteString(key))) = entries.next() {
+            let (first, token) = match entries.next() {
+                Some(entry) => entry,
+                None => break,
+            };
+            let mut last = first;
+            let mut depth = match token {
+                TokenRef::Dictionary | TokenRef::List => 1,
+                _ => 0,
+            };
+            while depth > 0 {
+                match entries.next() {
+                    Some((span, token)) => {
+                        match token {
+                            TokenRef::Dictionary | TokenRef::List => depth += 1,
+                            TokenRef::End => depth -= 1,
+                            _ => {}
+                        }
+                        last = span;
+                    }
+                    None => break,
+                }
+            }
+            ranges.insert(
+                String::from_utf8_lossy(key).into_owned(),
+                first.start..last.end,
+            );
+        }
+    }
+    Ok(ranges)
+}
+
+/// Cheaply estimates number of values in given slice of bytes, without
+/// allocating anything. The estimate is an upper bound, as dictionary keys
+/// are counted too, but the data is not validated, so the actual parsing
+/// may still fail.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::estimate_nodes;
+///
+/// assert_eq!(estimate_nodes(b"li1e4:spame"), Ok(3));
+/// ```
+pub fn estimate_nodes(data: &[u8]) -> Result<usize, Error> {
+    let mut index = 0;
+    let mut count = 0;
+    while let Some(&byte) = data.get(index) {
+        match byte {
+            b'd' | b'l' => index += 1,
+            b'e' => {
+                index += 1;
+                continue;
+            }
+            b'i' => {
+                index = match data[index..].iter().position(|&c| c == b'e') {
+                    Some(end) => index + end + 1,
+                    None => data.len(),
+                }
+            }
+            b'0'..=b'9' => {
+                let digits = data[index..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+                let len = data[index..index + digits].iter().fold(0usize, |len, c| {
+                    len.saturating_mul(10).saturating_add((c - b'0') as usize)
+                });
+                index += digits;
+                if data.get(index) != Some(&b':') {
+                    return Err(Error::Lexer(lexer::Error::ReadLen));
+                }
+                index = index.saturating_add(1).saturating_add(len);
+            }
+            c => return Err(Error::Lexer(lexer::Error::ReadFirstByte(c as char))),
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Value parsed by [`parse_lenient_reporting`], along with the
+/// non-canonical constructs encountered in the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOutcome {
+    pub value: Bencode,
+    pub warnings: Vec<Warning>,
+}
+
+/// Metrics of the value parsed by [`Bencode::from_bytes_with_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseStats {
+    /// Number of nested lists and dictionaries on the deepest path.
+    pub max_depth: usize,
+    /// Number of values, not counting keys of the dictionaries.
+    pub node_count: usize,
+}
+
+/// Parses given slice of bytes, accepting non-canonical constructs
+/// like unsorted dictionary keys or integers with leading zeros,
+/// but reporting each of them as a warning.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{parse_lenient_reporting, Bencode, Warning};
+///
+/// let outcome = parse_lenient_reporting(b"i007e").unwrap();
+/// assert_eq!(outcome.value, Bencode::Integer(7));
+/// assert_eq!(outcome.warnings, vec![Warning::LeadingZero { offset: 0 }]);
+/// ```
+pub fn parse_lenient_reporting(data: &[u8]) -> Result<ParseOutcome, Error> {
+    let spans = value_spans(data)?;
+    let value = parser::parse(owned_tokens(&spans)).map_err(Error::Parser)?;
+    let warnings = canonical::check(data, &spans);
+    Ok(ParseOutcome { value, warnings })
+}
+
+/// Parses given slice of bytes, accepting non-canonical constructs, and
+/// encodes it back in canonical form, with sorted dictionary keys and
+/// integers without leading zeros.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::parse_and_canonicalize;
+///
+/// let left = parse_and_canonicalize(b"d3:fooi042e3:bar4:spame").unwrap();
+/// assert_eq!(left, b"d3:bar4:spam3:fooi42ee".to_vec());
+/// ```
+pub fn parse_and_canonicalize(data: &[u8]) -> Result<Vec<u8>, Error> {
+    parse_lenient_reporting(data).map(|outcome| outcome.value.into_bytes())
+}
+
+/// Parses given slice of bytes like `parse_and_canonicalize` does, but
+/// returns the parsed value along with its canonical encoding. If the data
+/// is already canonical, it is copied instead of being encoded again.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{parse_with_canonical, Bencode};
+///
+/// let (value, canonical) = parse_with_canonical(b"li042ee").unwrap();
+/// assert_eq!(value, Bencode::List(vec![Bencode::Integer(42)]));
+/// assert_eq!(canonical, b"li42ee".to_vec());
+/// ```
+pub fn parse_with_canonical(data: &[u8]) -> Result<(Bencode, Vec<u8>), Error> {
+    let ParseOutcome { value, warnings } = parse_lenient_reporting(data)?;
+    let len = value.encoded_len();
+    // Constructs not reported as warnings, like length prefixes with
+    // leading zeros, make the data longer than needed.
+    let canonical = if warnings.is_empty() && len == data.len() {
+        data.to_vec()
+    } else {
+        let mut res = Vec::with_capacity(len);
+        // Writing into vector of bytes cannot fail.
+        value.write_to(&mut res).unwrap();
+        res
+    };
+    Ok((value, canonical))
+}
+
+/// Parses as much of given slice of bytes as possible. Returns value built
+/// from the data preceding the first complication (with unfinished lists
+/// and dictionaries closed) along with that complication, if any.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{parse_recover, Bencode};
+///
+/// let (value, err) = parse_recover(b"li1ei2e");
+/// assert_eq!(value, Some(Bencode::List(vec![Bencode::Integer(1), Bencode::Integer(2)])));
+/// assert!(err.is_some());
+/// ```
+pub fn parse_recover(data: &[u8]) -> (Option<Bencode>, Option<Error>) {
+    let (tokens, lexer_err) = lexer::parse_recover(data);
+    let (value, parser_err) = parser::parse_recover(tokens);
+    match lexer_err {
+        Some((_, err)) => (value, Some(Error::Lexer(err))),
+        None => (value, parser_err.map(Error::Parser)),
+    }
+}
+
+impl Bencode {
+    /// Renders as much of given, possibly corrupted, data as possible, the
+    /// same way as `Display` does, followed by the note about where and why
+    /// parsing stopped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let left = Bencode::try_display_prefix(b"li1eixee");
+    /// assert!(left.starts_with("[\n  1\n]"));
+    /// assert!(left.contains("Parsing stopped at byte 4"));
+    /// ```
+    pub fn try_display_prefix(data: &[u8]) -> String {
+        let (tokens, lexer_err) = lexer::parse_recover(data);
+        let (value, parser_err) = parser::parse_recover(tokens);
+
+        let mut res = match value {
+            Some(value) => value.to_string(),
+            None => String::from("<nothing recovered>"),
+        };
+        match (lexer_err, parser_err) {
+            (Some((offset, err)), _) => res.push_str(&format!(
+                "\nParsing stopped at byte {}: {}",
+                offset,
+                Error::Lexer(err)
+            )),
+            (None, Some(err)) => {
+                res.push_str(&format!("\nParsing stopped: {}", Error::Parser(err)))
+            }
+            (None, None) => {}
+        }
+        res
+    }
+
+    /// Parses given slice of bytes, failing with `DepthLimitExceeded` if
+    /// lists and dictionaries are nested deeper than `max_depth`. Parsing
+    /// with `TryFrom` limits the depth to
+    /// [`DEFAULT_MAX_DEPTH`](parser::DEFAULT_MAX_DEPTH).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::{parser, Bencode, Error};
+    ///
+    /// assert!(Bencode::from_bytes_with_depth(b"llee", 2).is_ok());
+    /// assert_eq!(
+    ///     Bencode::from_bytes_with_depth(b"llee", 1),
+    ///     Err(Error::Parser(parser::Error::DepthLimitExceeded))
+    /// );
+    /// ```
+    pub fn from_bytes_with_depth(data: &[u8], max_depth: usize) -> Result<Bencode, Error> {
+        from_bytes_with_depth(data, max_depth)
+    }
+
+    /// Parses given slice of bytes like `from_bytes` does, but additionally
+    /// requires keys of every dictionary to appear in sorted order, without
+    /// duplicates, as the specification demands. Returns
+    /// `Error::UnsortedKeys` with offset of the first misplaced key otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::{Bencode, Error};
+    ///
+    /// assert!(Bencode::from_bytes_strict(b"d3:bari1e3:fooi2ee").is_ok());
+    /// assert_eq!(
+    ///     Bencode::from_bytes_strict(b"d3:fooi1e3:bari2ee"),
+    ///     Err(Error::UnsortedKeys { offset: 9 })
+    /// );
+    /// ```
+    pub fn from_bytes_strict(data: &[u8]) -> Result<Bencode, Error> {
+        let spans = value_spans(data)?;
+        let (value, _) = from_value_spans(data, &spans, parser::DEFAULT_MAX_DEPTH)?;
+        let unsorted = canonical::unsorted_keys(&spans).next();
+        match unsorted {
+            Some((span, _)) => Err(Error::UnsortedKeys { offset: span.start }),
+            None => Ok(value),
+        }
+    }
+
+    /// Parses given owned vector of bytes, dropping it afterwards.
+    ///
+    /// `TryFrom<Vec<u8>>` cannot parse, because it is already provided by
+    /// the standard library for every `From<Vec<u8>>` conversion, which wraps
+    /// bytes in `Bencode::ByteString` instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let data = b"li42ee".to_vec();
+    /// let value = Bencode::from_vec(data).unwrap();
+    /// assert_eq!(value, Bencode::List(vec![Bencode::Integer(42)]));
+    /// ```
+    pub fn from_vec(data: Vec<u8>) -> Result<Bencode, Error> {
+        from_bytes(&data)
+    }
+
+    /// Parses given owned string, dropping it afterwards.
+    ///
+    /// `TryFrom<String>` cannot parse, for the same reason as
+    /// `TryFrom<Vec<u8>>` described in `Bencode::from_vec`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let data = String::from("4:spam");
+    /// let value = Bencode::from_string(data).unwrap();
+    /// assert_eq!(value, Bencode::ByteString(b"spam".to_vec()));
+    /// ```
+    pub fn from_string(data: String) -> Result<Bencode, Error> {
+        from_str(&data)
+    }
+
+    /// Parses given slice of bytes like `from_bytes` does, and additionally
+    /// returns maximum nesting depth and number of values found in it, so
+    /// callers can reject data exceeding their limits. Both are counted
+    /// while parsing, without walking the value afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::{Bencode, ParseStats};
+    ///
+    /// let (_, stats) = Bencode::from_bytes_with_stats(b"d3:fooli1ei2eee").unwrap();
+    /// assert_eq!(stats, ParseStats { max_depth: 2, node_count: 4 });
+    /// ```
+    pub fn from_bytes_with_stats(data: &[u8]) -> Result<(Bencode, ParseStats), Error> {
+        from_bytes_with_depth_stats(data, parser::DEFAULT_MAX_DEPTH)
+    }
+
+    /// Parses given slice of bytes and returns offset and description of the
+    /// first non-canonical construct found in it, or `None` if it is canonical.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let (offset, _) = Bencode::first_non_canonical(b"li1ei-0ee").unwrap().unwrap();
+    /// assert_eq!(offset, 4);
+    /// assert_eq!(Bencode::first_non_canonical(b"li1ei0ee"), Ok(None));
+    /// ```
+    pub fn first_non_canonical(data: &[u8]) -> Result<Option<(usize, String)>, Error> {
+        let outcome = parse_lenient_reporting(data)?;
+        Ok(outcome
+            .warnings
+            .into_iter()
+            .min_by_key(Warning::offset)
+            .map(|warning| (warning.offset(), warning.to_string())))
+    }
+}
+
+/// Parses value split into many slices of bytes, as if they were concatenated.
+/// Data is copied, unless at most one of the slices is non-empty.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{from_slices, Bencode};
+///
+/// let left = from_slices(&[b"li4", b"2e4:sp", b"ame"]).unwrap();
+/// let right = Bencode::List(vec![Bencode::Integer(42), Bencode::ByteString("spam".into())]);
+/// assert_eq!(left, right);
+/// ```
+pub fn from_slices(slices: &[&[u8]]) -> Result<Bencode, Error> {
+    let mut non_empty = slices.iter().filter(|slice| !slice.is_empty());
+    match (non_empty.next(), non_empty.next()) {
+        (None, _) => from_bytes(&[]),
+        (Some(slice), None) => from_bytes(slice),
+        _ => from_bytes(&slices.concat()),
+    }
+}
+
 fn from_str(data: &str) -> Result<Bencode, Error> {
     from_bytes(data.as_bytes())
 }
@@ -99,7 +811,7 @@ impl<'a> TryFrom<&'a str> for Bencode {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
+    use crate::instrument;
 
     #[test]
     fn test_from_str() {
@@ -116,6 +828,39 @@ mod tests {
         assert_eq!(left, right);
     }
 
+    #[test]
+    fn test_from_string() {
+        let left = Bencode::from_string("d3:bar4:spam3:fooi42ee".to_string()).unwrap();
+
+        let right = {
+            let mut dict = HashMap::new();
+            dict.insert("bar".into(), Bencode::ByteString("spam".into()));
+            dict.insert("foo".into(), Bencode::Integer(42));
+            Bencode::Dictionary(dict)
+        };
+
+        assert_eq!(left, right);
+        assert_eq!(Bencode::from_string(String::new()), Err(Error::EmptyInput));
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let left = Bencode::from_vec(b"l4:spami42ei666e5:tumore".to_vec()).unwrap();
+
+        let right = Bencode::List(vec![
+            Bencode::ByteString("spam".into()),
+            Bencode::Integer(42),
+            Bencode::Integer(666),
+            Bencode::ByteString("tumor".into()),
+        ]);
+
+        assert_eq!(left, right);
+        assert_eq!(
+            Bencode::from_vec(b"li1e".to_vec()),
+            Err(Error::Parser(parser::Error::NoEndList))
+        );
+    }
+
     #[test]
     fn test_from_bytes() {
         use std::convert::TryInto;
@@ -130,4 +875,471 @@ mod tests {
 
         assert_eq!(left, right);
     }
+
+    #[test]
+    fn test_try_display_prefix() {
+        let left = Bencode::try_display_prefix(b"d3:barl4:spami1eixee");
+        let right = concat!(
+            "{\n  \"bar\": [\n    \"spam\",\n    1\n  ]\n}",
+            "\nParsing stopped at byte 16: Lexer Error: ",
+            "Integers can only be composed of numeric characters."
+        );
+        assert_eq!(left, right);
+
+        let left = Bencode::try_display_prefix(b"i42e");
+        assert_eq!(left, "42");
+
+        let left = Bencode::try_display_prefix("l".repeat(50_000).as_bytes());
+        assert!(left.ends_with("Lists and dictionaries are nested deeper than allowed."));
+    }
+
+    #[test]
+    fn test_parse_with_budget() {
+        let data = format!("l{}e", "i1e".repeat(1000));
+
+        assert_eq!(
+            parse_with_budget(data.as_bytes(), 100),
+            Err(Error::BudgetExhausted)
+        );
+        assert_eq!(
+            parse_with_budget(data.as_bytes(), 2003),
+            Ok(Bencode::List(vec![Bencode::Integer(1); 1000]))
+        );
+        // All tokens fit in the budget, but the list does not.
+        assert_eq!(
+            parse_with_budget(data.as_bytes(), 2002),
+            Err(Error::BudgetExhausted)
+        );
+        assert_eq!(parse_with_budget(b"", 10), Err(Error::EmptyInput));
+        assert_eq!(
+            parse_with_budget(b"i1ei2e", 10),
+            Err(Error::TrailingData { offset: 3 })
+        );
+    }
+
+    #[test]
+    fn test_parse_lenient_reporting() {
+        let left = parse_lenient_reporting(b"d3:fooi042e3:bar4:spame").unwrap();
+
+        let value = {
+            let mut dict = HashMap::new();
+            dict.insert("bar".into(), Bencode::ByteString("spam".into()));
+            dict.insert("foo".into(), Bencode::Integer(42));
+            Bencode::Dictionary(dict)
+        };
+        let warnings = vec![
+            Warning::LeadingZero { offset: 6 },
+            Warning::UnsortedKey {
+                offset: 11,
+                key: "bar".into(),
+            },
+        ];
+
+        assert_eq!(left, ParseOutcome { value, warnings });
+    }
+
+    #[test]
+    fn test_encodings_equal() {
+        let left = b"d3:fooi42e3:barl4:spami1eee";
+        let right = b"d3:barl4:spami1ee3:fooi42ee";
+        assert_eq!(encodings_equal(left, right), Ok(true));
+        assert_eq!(encodings_equal(left, b"d3:fooi42ee"), Ok(false));
+        assert_eq!(
+            encodings_equal(left, b"ixe"),
+            Err(Error::Lexer(lexer::Error::ReadInt))
+        );
+    }
+
+    #[test]
+    fn test_key_value_ranges() {
+        let data = b"d4:infod6:lengthi10ee4:name4:spame";
+        let left = key_value_ranges(data).unwrap();
+
+        let right = {
+            let mut ranges = HashMap::new();
+            ranges.insert("info".to_string(), 7..21);
+            ranges.insert("name".to_string(), 27..33);
+            ranges
+        };
+
+        assert_eq!(left, right);
+        assert_eq!(&data[left["info"].clone()], b"d6:lengthi10ee");
+        assert_eq!(key_value_ranges(b"li1ee"), Ok(HashMap::new()));
+    }
+
+    #[test]
+    fn test_from_slices() {
+        let data = b"d3:bar4:spam3:fooli42ei-1eee";
+        let left = from_slices(&[&data[..5], &data[5..14], &data[14..]]).unwrap();
+        assert_eq!(left, from_bytes(data).unwrap());
+
+        let left = from_slices(&[b"", data, b""]).unwrap();
+        assert_eq!(left, from_bytes(data).unwrap());
+    }
+
+    #[test]
+    fn test_parse_after_prefix() {
+        let magic = b"\x7fBEN";
+        assert_eq!(
+            parse_after_prefix(b"\x7fBENi1e", magic),
+            Ok(Bencode::Integer(1))
+        );
+        assert_eq!(
+            parse_after_prefix(b"\x7fBENi1ei2e", magic),
+            Err(Error::TrailingData { offset: 7 })
+        );
+        assert_eq!(
+            parse_after_prefix(b"\x7fBEXi1e", magic),
+            Err(Error::MissingPrefix)
+        );
+        assert_eq!(
+            parse_after_prefix(b"\x7fBENi1exyz", magic),
+            Err(Error::TrailingData { offset: 7 })
+        );
+        assert_eq!(
+            parse_after_prefix(b"\x7fBENli1ei01ee", magic),
+            Err(Error::NonCanonicalInteger { offset: 8 })
+        );
+    }
+
+    #[test]
+    fn test_parse_and_canonicalize() {
+        let data = b"d4:spaml1:b1:ae3:cow3:mooe";
+        let left = parse_and_canonicalize(data).unwrap();
+        assert_eq!(left, b"d3:cow3:moo4:spaml1:b1:aee".to_vec());
+        assert_eq!(parse_and_canonicalize(&left).unwrap(), left);
+        assert_eq!(
+            parse_and_canonicalize(b"l"),
+            Err(Error::Parser(parser::Error::NoEndList))
+        );
+        assert_eq!(
+            parse_and_canonicalize(b"d3:fooi1eeGARBAGE"),
+            Err(Error::TrailingData { offset: 10 })
+        );
+    }
+
+    #[test]
+    fn test_first_non_canonical() {
+        let first = |data: &[u8]| Bencode::first_non_canonical(data).unwrap();
+
+        let unsorted = Warning::UnsortedKey {
+            offset: 9,
+            key: "bar".into(),
+        };
+        assert_eq!(
+            first(b"d3:fooi1e3:bari2ee"),
+            Some((9, unsorted.to_string()))
+        );
+        let leading_zero = Warning::LeadingZero { offset: 4 };
+        assert_eq!(first(b"li1ei01ei-0ee"), Some((4, leading_zero.to_string())));
+        let negative_zero = Warning::NegativeZero { offset: 1 };
+        assert_eq!(first(b"li-0ei01ee"), Some((1, negative_zero.to_string())));
+        assert_eq!(first(b"d3:bari2e3:fooi1ee"), None);
+        assert_eq!(
+            Bencode::first_non_canonical(b"ixe"),
+            Err(Error::Lexer(lexer::Error::ReadInt))
+        );
+    }
+
+    #[test]
+    fn test_estimate_nodes() {
+        fn count(value: &Bencode) -> usize {
+            match value {
+                Bencode::Integer(_) | Bencode::ByteString(_) => 1,
+                Bencode::List(vec) => 1 + vec.iter().map(count).sum::<usize>(),
+                Bencode::Dictionary(map) => 1 + map.values().map(count).sum::<usize>(),
+                Bencode::DictionaryBytes(map) => 1 + map.values().map(count).sum::<usize>(),
+            }
+        }
+
+        let inputs: &[&[u8]] = &[
+            b"i42e",
+            b"4:spam",
+            b"le",
+            b"l4:dlie2:i1e",
+            b"d3:bar4:spam3:fool1:ai42eee",
+            b"d4:infod6:lengthi1024e4:name4:spamee",
+        ];
+        for data in inputs {
+            let actual = count(&from_bytes(data).unwrap());
+            assert!(estimate_nodes(data).unwrap() >= actual);
+        }
+        assert_eq!(estimate_nodes(b"l4:dlie2:i1e"), Ok(3));
+        assert_eq!(estimate_nodes(b""), Ok(0));
+        assert_eq!(
+            estimate_nodes(b"4spam"),
+            Err(Error::Lexer(lexer::Error::ReadLen))
+        );
+    }
+
+    #[test]
+    fn test_integer_range() {
+        let values = [i64::MAX, i64::MIN, i32::MAX as i64 + 1, i32::MIN as i64 - 1];
+        for &n in values.iter() {
+            let data = format!("i{}e", n);
+            let value = from_str(&data).unwrap();
+            assert_eq!(value, Bencode::Integer(n));
+            assert_eq!(value.into_bytes(), data.into_bytes());
+        }
+        assert_eq!(from_str("i2147483648e"), Ok(Bencode::Integer(2147483648)));
+        assert_eq!(
+            from_str("i9223372036854775808e"),
+            Err(Error::Lexer(lexer::Error::ReadInt))
+        );
+    }
+
+    #[test]
+    fn test_binary_byte_string_round_trip() {
+        let data = b"4:\x00\x01\x02\x03";
+        let value = from_bytes(data).unwrap();
+        assert_eq!(value, Bencode::ByteString(vec![0, 1, 2, 3]));
+        assert_eq!(value.into_bytes(), data.to_vec());
+
+        let data = b"d6:piecesl2:\xff\xfe1:\x80ee";
+        assert_eq!(from_bytes(data).unwrap().into_bytes(), data.to_vec());
+        assert_eq!(
+            from_bytes(b"d1:\xffi1ee"),
+            Ok(Bencode::DictionaryBytes(
+                vec![(vec![0xff], Bencode::Integer(1))]
+                    .into_iter()
+                    .collect()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        assert_eq!(
+            Bencode::try_from("i42ei99e"),
+            Err(Error::TrailingData { offset: 4 })
+        );
+        assert_eq!(
+            Bencode::try_from(b"d3:fooi1eed3:bari2ee".as_ref()),
+            Err(Error::TrailingData { offset: 10 })
+        );
+        assert_eq!(
+            Bencode::try_from("i42ex"),
+            Err(Error::TrailingData { offset: 4 })
+        );
+        assert_eq!(Bencode::try_from("i42e"), Ok(Bencode::Integer(42)));
+    }
+
+    #[test]
+    fn test_trailing_data_entry_points() {
+        let trailing = Error::TrailingData { offset: 4 };
+        assert_eq!(key_value_ranges(b"i42ei99e"), Err(trailing.clone()));
+        assert_eq!(parse_lenient_reporting(b"i42ei99e"), Err(trailing.clone()));
+        assert_eq!(BencodeRef::parse(b"i42ei99e"), Err(trailing.clone()));
+        assert_eq!(Bencode::from_bytes_strict(b"i42ex"), Err(trailing));
+
+        assert_eq!(key_value_ranges(b""), Err(Error::EmptyInput));
+        assert_eq!(parse_lenient_reporting(b""), Err(Error::EmptyInput));
+        assert_eq!(BencodeRef::parse(b""), Err(Error::EmptyInput));
+
+        // Lexer errors within the value are still reported as such.
+        assert_eq!(
+            parse_lenient_reporting(b"li4xee"),
+            Err(Error::Lexer(lexer::Error::ReadInt))
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_with_depth() {
+        let data = format!("{}{}", "l".repeat(10_000), "e".repeat(10_000));
+        assert_eq!(
+            Bencode::from_bytes_with_depth(data.as_bytes(), 128),
+            Err(Error::Parser(parser::Error::DepthLimitExceeded))
+        );
+        assert_eq!(
+            Bencode::try_from(data.as_str()),
+            Err(Error::Parser(parser::Error::DepthLimitExceeded))
+        );
+
+        let data = format!("{}{}", "l".repeat(128), "e".repeat(128));
+        assert!(Bencode::try_from(data.as_str()).is_ok());
+    }
+
+    #[test]
+    fn test_from_bytes_deeply_nested() {
+        let depth = 50_000;
+        let data = format!("{}i1e{}", "l".repeat(depth), "e".repeat(depth));
+        let value = Bencode::from_bytes_with_depth(data.as_bytes(), depth).unwrap();
+        assert_eq!(value.encoded_len(), data.len());
+        assert_eq!(value.into_bytes(), data.as_bytes());
+
+        // Neither encoding nor dropping the tree recurses.
+        let value = Bencode::from_bytes_with_depth(data.as_bytes(), depth).unwrap();
+        drop(value);
+
+        assert_eq!(
+            Bencode::from_bytes_with_depth(data.as_bytes(), depth - 1),
+            Err(Error::Parser(parser::Error::DepthLimitExceeded))
+        );
+    }
+
+    #[test]
+    fn test_non_canonical_integer() {
+        assert_eq!(
+            Bencode::try_from("i03e"),
+            Err(Error::NonCanonicalInteger { offset: 0 })
+        );
+        assert_eq!(
+            Bencode::try_from("i-0e"),
+            Err(Error::NonCanonicalInteger { offset: 0 })
+        );
+        assert_eq!(
+            Bencode::try_from("li1ei00ee"),
+            Err(Error::NonCanonicalInteger { offset: 4 })
+        );
+        assert_eq!(Bencode::try_from("i0e"), Ok(Bencode::Integer(0)));
+        assert_eq!(Bencode::try_from("i-1e"), Ok(Bencode::Integer(-1)));
+        assert_eq!(
+            parse_lenient_reporting(b"i03e").unwrap().value,
+            Bencode::Integer(3)
+        );
+    }
+
+    #[test]
+    fn test_parse_with_canonical() {
+        let inputs: &[&[u8]] = &[
+            b"d3:bar4:spam3:fooi42ee",
+            b"d3:fooi42e3:bar4:spame",
+            b"li-0ei007e04:spame",
+        ];
+        for data in inputs {
+            let (value, canonical) = parse_with_canonical(data).unwrap();
+            assert_eq!(canonical, value.into_bytes());
+        }
+        assert_eq!(
+            parse_with_canonical(b"i1ei2e"),
+            Err(Error::TrailingData { offset: 3 })
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_strict() {
+        let sorted = b"d1:ai1e1:bd1:xi2e1:yi3ee2:bbi4ee";
+        assert_eq!(Bencode::from_bytes_strict(sorted), from_bytes(sorted));
+
+        // Nested dictionary has its keys swapped.
+        let unsorted = b"d1:ai1e1:bd1:yi3e1:xi2ee2:bbi4ee";
+        assert!(from_bytes(unsorted).is_ok());
+        assert_eq!(
+            Bencode::from_bytes_strict(unsorted),
+            Err(Error::UnsortedKeys { offset: 17 })
+        );
+
+        // Data is lexed once, and only the stack of keys comes on top
+        // of allocations made by `from_bytes`.
+        let (_, plain) = instrument::count(|| drop(from_bytes(unsorted)));
+        let (_, strict) = instrument::count(|| drop(Bencode::from_bytes_strict(unsorted)));
+        assert_eq!(strict.allocations, plain.allocations + 1);
+
+        let duplicate = b"d1:ai1e1:ai2ee";
+        assert_eq!(
+            Bencode::from_bytes_strict(duplicate),
+            Err(Error::UnsortedKeys { offset: 7 })
+        );
+
+        assert_eq!(
+            Bencode::from_bytes_strict(b"d1:ai1eei1e"),
+            Err(Error::TrailingData { offset: 8 })
+        );
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(Bencode::try_from(&[][..]), Err(Error::EmptyInput));
+        assert_eq!(Bencode::try_from(""), Err(Error::EmptyInput));
+        assert_eq!(from_slices(&[b"", b""]), Err(Error::EmptyInput));
+        assert_eq!(
+            Bencode::from_reader(io::Cursor::new(b"")),
+            Err(Error::EmptyInput)
+        );
+    }
+
+    #[test]
+    fn test_non_utf8_key_round_trip() {
+        let data = b"d1:ai1e1:\x80d3:fooi3ee2:\xff\xfeli2eee";
+        let value = from_bytes(data).unwrap();
+
+        let dict = match &value {
+            Bencode::DictionaryBytes(dict) => dict,
+            other => panic!("unexpected value: {:?}", other),
+        };
+        assert_eq!(dict.len(), 3);
+        assert_eq!(dict[b"a".as_ref()], Bencode::Integer(1));
+        assert!(matches!(dict[b"\x80".as_ref()], Bencode::Dictionary(_)));
+
+        assert_eq!(value.encoded_len(), data.len());
+        assert_eq!(value.to_string().lines().nth(2), Some("  <80>: {"));
+        assert_eq!(value.clone().into_bytes(), data.to_vec());
+
+        let mut written = Vec::new();
+        value.write_to(&mut written).unwrap();
+        assert_eq!(written, data.to_vec());
+    }
+
+    #[test]
+    fn test_differ_only_in_order() {
+        let canonical = b"d4:infod6:lengthi1e4:name4:spame4:listli1ei2eee";
+        let permuted = b"d4:listli1ei2ee4:infod4:name4:spam6:lengthi1eee";
+        assert_eq!(differ_only_in_order(canonical, permuted), Ok(true));
+        assert_eq!(differ_only_in_order(permuted, canonical), Ok(true));
+        assert_eq!(differ_only_in_order(canonical, canonical), Ok(false));
+
+        // Order of list elements is significant.
+        let reversed = b"d4:infod6:lengthi1e4:name4:spame4:listli2ei1eee";
+        assert_eq!(differ_only_in_order(canonical, reversed), Ok(false));
+
+        let padded = b"d4:infod6:lengthi1e4:name04:spame4:listli1ei2eee";
+        assert_eq!(differ_only_in_order(canonical, padded), Ok(false));
+
+        assert_eq!(
+            differ_only_in_order(canonical, b"d4:info"),
+            Err(Error::Parser(parser::Error::NoEndDictionary))
+        );
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        assert_eq!(
+            Bencode::try_from(b"i42".as_ref()),
+            Err(Error::Lexer(lexer::Error::UnexpectedEof {
+                context: "integer"
+            }))
+        );
+        assert_eq!(
+            Bencode::try_from(b"i4x2e".as_ref()),
+            Err(Error::Lexer(lexer::Error::ReadInt))
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_with_stats() {
+        let data = b"d4:infod5:filesld6:lengthi1eed6:lengthi2eee4:name4:spame3:urllee";
+        let (value, stats) = Bencode::from_bytes_with_stats(data).unwrap();
+        assert_eq!(value, from_bytes(data).unwrap());
+        assert_eq!(
+            stats,
+            ParseStats {
+                max_depth: 4,
+                node_count: 9
+            }
+        );
+
+        let (_, stats) = Bencode::from_bytes_with_stats(b"i42e").unwrap();
+        assert_eq!(
+            stats,
+            ParseStats {
+                max_depth: 0,
+                node_count: 1
+            }
+        );
+
+        assert_eq!(
+            Bencode::from_bytes_with_stats(b"li1e"),
+            Err(Error::Parser(parser::Error::NoEndList))
+        );
+    }
 }
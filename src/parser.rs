@@ -1,27 +1,147 @@
 //! Parses module contains data structures and procedures
 //! related to parsing tokenized input.
 use crate::lexer::Token;
+use crate::writer::BencodeWriter;
+use crate::ParseStats;
 
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io::{self, Write};
 use std::{error, fmt};
+use std::{mem, str, vec};
 
 /// Bencode is recursive data structure which
 /// works as representation of all possible data
 /// that can be encoded with bencoding.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Bencode {
     /// an be positive or negative.
     Integer(i64),
     /// Fixed-length string of bytes.
-    ByteString(String),
+    ByteString(Vec<u8>),
     /// List of bencoded values.
     List(Vec<Bencode>),
     /// Associative array where keys can be only strings
     /// and values can be any of bencoding data structures.
     Dictionary(HashMap<String, Bencode>),
+    /// Associative array where at least one of the keys is
+    /// a byte string that is not valid UTF-8.
+    DictionaryBytes(HashMap<Vec<u8>, Bencode>),
+}
+
+impl Drop for Bencode {
+    /// Drops nested lists and dictionaries one by one, from explicit stack
+    /// instead of the call stack, so arbitrarily deep values can be dropped.
+    /// Values without nested containers are dropped without allocating.
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_nested(&mut stack);
+        while let Some(mut value) = stack.pop() {
+            value.take_nested(&mut stack);
+        }
+    }
 }
 
 impl Bencode {
+    /// Moves lists and dictionaries nested directly in `Bencode` onto given
+    /// stack, leaving it without them.
+    fn take_nested(&mut self, stack: &mut Vec<Bencode>) {
+        let is_container = |value: &Bencode| {
+            matches!(
+                value,
+                Bencode::List(_) | Bencode::Dictionary(_) | Bencode::DictionaryBytes(_)
+            )
+        };
+        match self {
+            Bencode::Integer(_) | Bencode::ByteString(_) => {}
+            Bencode::List(vec) => {
+                if vec.iter().any(is_container) {
+                    stack.extend(vec.drain(..).filter(is_container));
+                }
+            }
+            Bencode::Dictionary(map) => {
+                if map.values().any(is_container) {
+                    stack.extend(map.drain().map(|(_, value)| value).filter(is_container));
+                }
+            }
+            Bencode::DictionaryBytes(map) => {
+                if map.values().any(is_container) {
+                    stack.extend(map.drain().map(|(_, value)| value).filter(is_container));
+                }
+            }
+        }
+    }
+
+    /// Builds dictionary from given key and value pairs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let dict = Bencode::dict_from_iter(vec![("foo".to_string(), Bencode::Integer(42))]);
+    /// assert_eq!(dict.into_bytes(), b"d3:fooi42ee".to_vec());
+    /// ```
+    pub fn dict_from_iter<I: IntoIterator<Item = (String, Bencode)>>(iter: I) -> Bencode {
+        Bencode::Dictionary(iter.into_iter().collect())
+    }
+
+    /// Builds list from given values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let list = Bencode::list_from_iter((1..=2).map(Bencode::Integer));
+    /// assert_eq!(list.into_bytes(), b"li1ei2ee".to_vec());
+    /// ```
+    pub fn list_from_iter<I: IntoIterator<Item = Bencode>>(iter: I) -> Bencode {
+        Bencode::List(iter.into_iter().collect())
+    }
+
+    /// Wraps given bytes into byte string, keeping them exactly as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let value = Bencode::byte_string_from_bytes(vec![0xff, 0x00]);
+    /// assert_eq!(value.into_bytes(), b"2:\xff\x00".to_vec());
+    /// ```
+    pub fn byte_string_from_bytes(bytes: Vec<u8>) -> Bencode {
+        Bencode::ByteString(bytes)
+    }
+
+    /// Wraps given bytes into byte string if they are valid UTF-8.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::{parser, Bencode};
+    ///
+    /// let value = Bencode::try_byte_string_from_utf8(b"spam".to_vec());
+    /// assert_eq!(value, Ok(Bencode::ByteString(b"spam".to_vec())));
+    ///
+    /// let value = Bencode::try_byte_string_from_utf8(vec![b'a', 0xff]);
+    /// assert_eq!(value, Err(parser::Error::InvalidUtf8 { valid_up_to: 1 }));
+    /// ```
+    pub fn try_byte_string_from_utf8(bytes: Vec<u8>) -> Result<Bencode, Error> {
+        match String::from_utf8(bytes) {
+            Ok(string) => Ok(Bencode::ByteString(string.into_bytes())),
+            Err(err) => Err(Error::InvalidUtf8 {
+                valid_up_to: err.utf8_error().valid_up_to(),
+            }),
+        }
+    }
+
+    /// Creates builder of the dictionary, equivalent to `DictBuilder::new`.
+    pub fn dict_builder() -> DictBuilder {
+        DictBuilder::new()
+    }
+
     /// Transforms `Bencode` into owned vector of bencoded bytes.
     ///
     /// # Examples
@@ -53,186 +173,1826 @@ impl Bencode {
     /// assert_eq!(left, right);
     /// ```
     pub fn into_bytes(self) -> Vec<u8> {
-        match self {
-            Bencode::Integer(n) => {
-                let res = format!("i{}e", n);
-                res.into_bytes()
-            }
-            Bencode::ByteString(s) => {
-                let mut res = Vec::new();
+        let mut res = Vec::new();
+        self.encode_into(&mut res);
+        res
+    }
 
-                let mut string = s.into_bytes();
-                let mut head = {
-                    let s = format!("{}:", string.len());
-                    s.into_bytes()
-                };
+    /// Transforms `Bencode` into owned vector of bencoded bytes, like
+    /// `into_bytes` does, but pre-allocates it with given capacity. Pass
+    /// `encoded_len` as capacity to encode with exactly one allocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let value = Bencode::ByteString("spam".into());
+    /// let bytes = value.clone().into_bytes_with_capacity(value.encoded_len());
+    /// assert_eq!(bytes, b"4:spam".to_vec());
+    /// ```
+    pub fn into_bytes_with_capacity(self, cap: usize) -> Vec<u8> {
+        let mut res = Vec::with_capacity(cap);
+        self.encode_into(&mut res);
+        res
+    }
 
-                res.append(&mut head);
-                res.append(&mut string);
-                res
-            }
-            Bencode::List(vec) => {
-                let mut res = Vec::new();
-                res.push('l' as u8);
-                vec.into_iter()
-                    .map(|elem| elem.into_bytes())
-                    .for_each(|elem| res.extend_from_slice(&elem));
-                res.push('e' as u8);
-                res
+    /// Appends bencoded bytes of `Bencode` to given buffer. Values that
+    /// are still to be encoded are kept on explicit stack instead of the
+    /// call stack, so arbitrarily deep values can be encoded.
+    fn encode_into(self, res: &mut Vec<u8>) {
+        let write_string = |res: &mut Vec<u8>, string: &[u8]| {
+            res.extend_from_slice(string.len().to_string().as_bytes());
+            res.push(b':');
+            res.extend_from_slice(string);
+        };
+        let mut stack = Vec::new();
+        let mut next = Some(Encoding::Value(self));
+        while let Some(encoding) = next.take().or_else(|| stack.pop()) {
+            let mut value = match encoding {
+                Encoding::Value(value) => value,
+                Encoding::Key(key) => {
+                    write_string(res, &key);
+                    continue;
+                }
+                Encoding::End => {
+                    res.push(b'e');
+                    continue;
+                }
+            };
+            match &mut value {
+                Bencode::Integer(n) => {
+                    res.push(b'i');
+                    res.extend_from_slice(n.to_string().as_bytes());
+                    res.push(b'e');
+                }
+                Bencode::ByteString(s) => write_string(res, s),
+                Bencode::List(vec) => {
+                    res.push(b'l');
+                    stack.reserve(vec.len() + 1);
+                    stack.push(Encoding::End);
+                    stack.extend(vec.drain(..).rev().map(Encoding::Value));
+                }
+                Bencode::Dictionary(map) => {
+                    res.push(b'd');
+                    stack.reserve(2 * map.len() + 1);
+                    stack.push(Encoding::End);
+                    let mut sorted_map = map.drain().collect::<Vec<(String, Bencode)>>();
+                    sorted_map.sort_by(|(first_key, _), (second_key, _)| second_key.cmp(first_key));
+                    for (key, value) in sorted_map {
+                        stack.push(Encoding::Value(value));
+                        stack.push(Encoding::Key(key.into_bytes()));
+                    }
+                }
+                Bencode::DictionaryBytes(map) => {
+                    res.push(b'd');
+                    stack.reserve(2 * map.len() + 1);
+                    stack.push(Encoding::End);
+                    let mut sorted_map = map.drain().collect::<Vec<(Vec<u8>, Bencode)>>();
+                    sorted_map.sort_by(|(first_key, _), (second_key, _)| second_key.cmp(first_key));
+                    for (key, value) in sorted_map {
+                        stack.push(Encoding::Value(value));
+                        stack.push(Encoding::Key(key));
+                    }
+                }
             }
-            Bencode::Dictionary(map) => {
-                let mut res = Vec::new();
-                res.push('d' as u8);
-
-                let sorted_map = {
-                    let mut sorted_map = map.into_iter().collect::<Vec<(String, Bencode)>>();
-                    sorted_map
-                        .sort_by(|(first_key, _), (second_key, _)| first_key.cmp(&second_key));
-                    sorted_map
-                };
-                sorted_map.into_iter().for_each(|(key, value)| {
-                    let mut key_bytes = key.clone().into_bytes();
-                    let mut head = {
-                        let s = format!("{}:", key_bytes.len());
-                        s.into_bytes()
-                    };
-                    res.append(&mut head);
-                    res.append(&mut key_bytes);
-                    res.append(&mut value.into_bytes());
-                });
+        }
+    }
 
-                res.push('e' as u8);
-                res
+    /// Transforms `Bencode` into owned vector of bencoded bytes, omitting given
+    /// top-level dictionary keys. Values other than dictionaries are encoded
+    /// like with `into_bytes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let torrent: Bencode = "d13:creation datei1e4:name4:spame".try_into().unwrap();
+    /// let left = torrent.into_bytes_excluding(&["creation date"]);
+    /// assert_eq!(left, b"d4:name4:spame".to_vec());
+    /// ```
+    pub fn into_bytes_excluding(mut self, keys: &[&str]) -> Vec<u8> {
+        match &mut self {
+            Bencode::Dictionary(map) => map.retain(|key, _| !keys.contains(&key.as_str())),
+            Bencode::DictionaryBytes(map) => {
+                map.retain(|key, _| !keys.iter().any(|excluded| excluded.as_bytes() == key))
             }
+            _ => {}
         }
+        self.into_bytes()
     }
-}
 
-/// Represents possible complications that can occur during parsing tokenized data.
-#[derive(Debug, Clone, PartialEq)]
-pub enum Error {
-    /// Empty token container provided.
-    NoTokens,
-    /// There is end token without need.
-    InvalidEndToken,
-    /// There is the list without explicit end token.
-    NoEndList,
-    /// There is a attempt to use type other than ByteString
-    /// as key in the dictionary.
-    InvalidDictionaryKey,
-    /// There is the dictionary without explicit end token.
-    NoEndDictionary,
-}
+    /// Transforms `Bencode` into owned vector of bencoded bytes, where top-level
+    /// dictionary keys are emitted in given order instead of sorted one.
+    /// Every key of the dictionary has to be present in `order` exactly once.
+    /// For values other than dictionaries `order` has to be empty. Keys
+    /// which are not valid UTF-8 cannot be ordered, so they are reported
+    /// as missing, converted lossily.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    /// use std::collections::HashMap;
+    ///
+    /// let left = {
+    ///     let mut d = HashMap::new();
+    ///     d.insert("one".into(), Bencode::Integer(1));
+    ///     d.insert("two".into(), Bencode::Integer(2));
+    ///     Bencode::Dictionary(d).into_bytes_with_order(&["two", "one"]).unwrap()
+    /// };
+    /// assert_eq!(left, b"d3:twoi2e3:onei1ee".to_vec());
+    /// ```
+    pub fn into_bytes_with_order(mut self, order: &[&str]) -> Result<Vec<u8>, Error> {
+        let mut map = match &mut self {
+            Bencode::Dictionary(map) => map
+                .drain()
+                .map(|(key, value)| (key.into_bytes(), value))
+                .collect::<HashMap<Vec<u8>, Bencode>>(),
+            Bencode::DictionaryBytes(map) => mem::take(map),
+            _ => {
+                return match order.first() {
+                    Some(key) => Err(Error::UnknownKey(key.to_string())),
+                    None => Ok(self.into_bytes()),
+                }
+            }
+        };
 
-impl error::Error for Error {}
+        let mut res = vec![b'd'];
+        for &key in order {
+            let value = map
+                .remove(key.as_bytes())
+                .ok_or_else(|| Error::UnknownKey(key.to_string()))?;
+            res.append(&mut Bencode::ByteString(key.into()).into_bytes());
+            res.append(&mut value.into_bytes());
+        }
+        if let Some(key) = map.keys().min() {
+            return Err(Error::MissingKey(String::from_utf8_lossy(key).into_owned()));
+        }
+        res.push(b'e');
+        Ok(res)
+    }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    /// Returns length of the bencoded form of `Bencode`, without building it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let value = Bencode::List(vec![Bencode::Integer(42)]);
+    /// assert_eq!(value.encoded_len(), 6);
+    /// ```
+    pub fn encoded_len(&self) -> usize {
+        let string_len = |s: &[u8]| s.len().to_string().len() + 1 + s.len();
+        let mut len = 0;
+        let mut stack = Vec::new();
+        let mut next = Some(self);
+        while let Some(value) = next.take().or_else(|| stack.pop()) {
+            len += match value {
+                Bencode::Integer(n) => n.to_string().len() + 2,
+                Bencode::ByteString(s) => string_len(s),
+                Bencode::List(vec) => {
+                    stack.extend(vec);
+                    2
+                }
+                Bencode::Dictionary(map) => {
+                    stack.extend(map.values());
+                    2 + map
+                        .keys()
+                        .map(|key| string_len(key.as_bytes()))
+                        .sum::<usize>()
+                }
+                Bencode::DictionaryBytes(map) => {
+                    stack.extend(map.values());
+                    2 + map.keys().map(|key| string_len(key)).sum::<usize>()
+                }
+            };
+        }
+        len
+    }
+
+    /// Returns sum of encoded sizes of all dictionary keys found in `Bencode`,
+    /// including their length prefixes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let value: Bencode = "d4:infod6:lengthi1eee".try_into().unwrap();
+    /// assert_eq!(value.total_key_bytes(), 6 + 8);
+    /// ```
+    pub fn total_key_bytes(&self) -> usize {
         match self {
-            Error::NoTokens => write!(f, "There are no tokens in the given vector."),
-            Error::InvalidEndToken => write!(f, "Too many end characters in given data."),
-            Error::NoEndList => write!(f, "There is list without end character in given data."),
-            Error::InvalidDictionaryKey => {
-                write!(f, "Dictionaries can only have byte strings as keys.")
-            }
-            Error::NoEndDictionary => write!(
-                f,
-                "There is dictionary without end character in given data."
-            ),
+            Bencode::Integer(_) | Bencode::ByteString(_) => 0,
+            Bencode::List(vec) => vec.iter().map(|elem| elem.total_key_bytes()).sum(),
+            Bencode::Dictionary(map) => map
+                .iter()
+                .map(|(key, value)| {
+                    key.len().to_string().len() + 1 + key.len() + value.total_key_bytes()
+                })
+                .sum(),
+            Bencode::DictionaryBytes(map) => map
+                .iter()
+                .map(|(key, value)| {
+                    key.len().to_string().len() + 1 + key.len() + value.total_key_bytes()
+                })
+                .sum(),
         }
     }
-}
 
-pub(crate) fn parse(tokens: Vec<Token>) -> Result<Bencode, Error> {
-    let mut tokens: Vec<Token> = tokens.into_iter().rev().collect();
-    match tokens.pop() {
-        Some(token) => parse_token(token, &mut tokens),
-        None => Err(Error::NoTokens),
+    /// Returns every top-level dictionary key along with the size of bencoded
+    /// form of its value, starting with the largest one. Returns `None`
+    /// if `Bencode` is not a dictionary. Keys which are not valid UTF-8
+    /// are converted lossily.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let torrent: Bencode = "d4:infod6:lengthi1ee4:name1:ae".try_into().unwrap();
+    /// let sizes = torrent.top_level_key_sizes().unwrap();
+    /// assert_eq!(sizes, vec![("info".to_string(), 13), ("name".to_string(), 3)]);
+    /// ```
+    pub fn top_level_key_sizes(&self) -> Option<Vec<(String, usize)>> {
+        let mut sizes = self
+            .sorted_entries()?
+            .into_iter()
+            .map(|(key, value)| {
+                let key = String::from_utf8_lossy(key).into_owned();
+                (key, value.encoded_len())
+            })
+            .collect::<Vec<(String, usize)>>();
+        sizes.sort_by(|(first_key, first_size), (second_key, second_size)| {
+            second_size
+                .cmp(first_size)
+                .then_with(|| first_key.cmp(second_key))
+        });
+        Some(sizes)
     }
-}
 
-fn parse_token(t: Token, tokens: &mut Vec<Token>) -> Result<Bencode, Error> {
-    match t {
-        Token::Dictionary => parse_dict(tokens, &mut HashMap::new()),
-        Token::List => parse_list(tokens, &mut Vec::new()),
-        Token::Integer(val) => Ok(Bencode::Integer(val)),
-        Token::ByteString(val) => Ok(Bencode::ByteString(val)),
-        Token::End => Err(Error::InvalidEndToken),
+    /// Serializes `Bencode` into given writer, without building
+    /// intermediate vector of bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let mut buf = Vec::new();
+    /// Bencode::Integer(2015).write_to(&mut buf).unwrap();
+    ///
+    /// assert_eq!(buf, b"i2015e".to_vec());
+    /// ```
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        BencodeWriter::new(writer).write_value(self)
     }
-}
 
-fn parse_list(tokens: &mut Vec<Token>, list: &mut Vec<Bencode>) -> Result<Bencode, Error> {
-    match tokens.pop() {
-        Some(Token::End) => Ok(Bencode::List(list.clone())),
-        Some(token) => {
-            list.push(parse_token(token, tokens)?);
-            parse_list(tokens, list)
+    /// Applies `f` to every dictionary key throughout the tree. Keys which
+    /// are not valid UTF-8 are left unchanged.
+    ///
+    /// Returns `Error::KeyCollision` if two keys of the same dictionary
+    /// are mapped to the same value. Dictionary with colliding keys is left
+    /// unchanged, but dictionaries visited before it stay transformed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut left = {
+    ///     let mut d = HashMap::new();
+    ///     d.insert("ONE".into(), Bencode::Integer(1));
+    ///     Bencode::Dictionary(d)
+    /// };
+    /// left.map_keys(|key| key.to_lowercase()).unwrap();
+    ///
+    /// assert_eq!(left.into_bytes(), b"d3:onei1ee".to_vec());
+    /// ```
+    pub fn map_keys<F: FnMut(&str) -> String + Copy>(&mut self, mut f: F) -> Result<(), Error> {
+        match self {
+            Bencode::List(vec) => vec.iter_mut().try_for_each(|elem| elem.map_keys(f)),
+            Bencode::Dictionary(map) => {
+                let mut mapped = HashMap::with_capacity(map.len());
+                for key in map.keys() {
+                    let new_key = f(key);
+                    if mapped.contains_key(&new_key) {
+                        return Err(Error::KeyCollision(new_key));
+                    }
+                    mapped.insert(new_key, key.clone());
+                }
+
+                map.values_mut().try_for_each(|value| value.map_keys(f))?;
+
+                *map = mapped
+                    .into_iter()
+                    .filter_map(|(new_key, key)| map.remove(&key).map(|value| (new_key, value)))
+                    .collect();
+                Ok(())
+            }
+            Bencode::DictionaryBytes(map) => {
+                map.values_mut().try_for_each(|value| value.map_keys(f))
+            }
+            Bencode::Integer(_) | Bencode::ByteString(_) => Ok(()),
         }
-        None => Err(Error::NoEndList),
     }
-}
 
-fn parse_dict(
-    tokens: &mut Vec<Token>,
-    dict: &mut HashMap<String, Bencode>,
-) -> Result<Bencode, Error> {
-    match tokens.pop() {
-        Some(Token::ByteString(key)) => {
-            let val = match tokens.pop() {
-                Some(token) => parse_token(token, tokens)?,
-                None => return Err(Error::NoEndDictionary),
-            };
-            dict.insert(key, val);
-            parse_dict(tokens, dict)
+    /// Applies given function to every integer found in `Bencode`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let mut value: Bencode = "d12:piece lengthi16384ee".try_into().unwrap();
+    /// value.map_integers(|n| n * 2);
+    /// assert_eq!(value.into_bytes(), b"d12:piece lengthi32768ee".to_vec());
+    /// ```
+    pub fn map_integers<F: FnMut(i64) -> i64 + Copy>(&mut self, mut f: F) {
+        match self {
+            Bencode::Integer(n) => *n = f(*n),
+            Bencode::List(vec) => vec.iter_mut().for_each(|elem| elem.map_integers(f)),
+            Bencode::Dictionary(map) => map.values_mut().for_each(|value| value.map_integers(f)),
+            Bencode::DictionaryBytes(map) => {
+                map.values_mut().for_each(|value| value.map_integers(f))
+            }
+            Bencode::ByteString(_) => {}
         }
-        Some(Token::End) => Ok(Bencode::Dictionary(dict.clone())),
-        _ => Err(Error::InvalidDictionaryKey),
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::Token;
+    /// Returns canonical description of the structure, which consists of types
+    /// and dictionary keys, but not scalar values. Integers are described
+    /// as `i`, byte strings as `s`, lists as `l[...]` and dictionaries
+    /// as `d{...}` with keys in sorted order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let left: Bencode = "d3:fooi42e3:barl4:spamee".try_into().unwrap();
+    /// assert_eq!(left.shape_fingerprint(), r#"d{"bar":l[s],"foo":i}"#);
+    /// ```
+    pub fn shape_fingerprint(&self) -> String {
+        match self {
+            Bencode::Integer(_) => String::from("i"),
+            Bencode::ByteString(_) => String::from("s"),
+            Bencode::List(vec) => {
+                let elems = vec
+                    .iter()
+                    .map(|elem| elem.shape_fingerprint())
+                    .collect::<Vec<String>>();
+                format!("l[{}]", elems.join(","))
+            }
+            Bencode::Dictionary(map) => {
+                let mut sorted_map = map.iter().collect::<Vec<(&String, &Bencode)>>();
+                sorted_map.sort_by_key(|(key, _)| *key);
 
-    #[test]
-    fn test_parse_list() {
-        let tokens = vec![
-            Token::List,
-            Token::Integer(55),
-            Token::ByteString("str".into()),
-            Token::End,
-        ];
-        let left = parse(tokens).unwrap();
-        let right = Bencode::List(vec![
-            Bencode::Integer(55),
-            Bencode::ByteString("str".into()),
-        ]);
-        assert_eq!(left, right);
+                let entries = sorted_map
+                    .into_iter()
+                    .map(|(key, value)| format!("{:?}:{}", key, value.shape_fingerprint()))
+                    .collect::<Vec<String>>();
+                format!("d{{{}}}", entries.join(","))
+            }
+            Bencode::DictionaryBytes(map) => {
+                let mut sorted_map = map.iter().collect::<Vec<(&Vec<u8>, &Bencode)>>();
+                sorted_map.sort_by_key(|(key, _)| *key);
+
+                let entries = sorted_map
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let key = String::from_utf8_lossy(key);
+                        format!("{:?}:{}", key, value.shape_fingerprint())
+                    })
+                    .collect::<Vec<String>>();
+                format!("d{{{}}}", entries.join(","))
+            }
+        }
     }
 
-    #[test]
-    fn test_parse_nested_list() {
-        let tokens = vec![
-            Token::List,
-            Token::Integer(55),
-            Token::List,
-            Token::ByteString("str".into()),
-            Token::End,
-            Token::End,
-        ];
-        let left = parse(tokens).unwrap();
-        let right = Bencode::List(vec![
-            Bencode::Integer(55),
-            Bencode::List(vec![Bencode::ByteString("str".into())]),
-        ]);
-        assert_eq!(left, right);
+    /// Returns value stored under given key of the dictionary, or `None`
+    /// if the key is absent or `Bencode` is not a dictionary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let torrent: Bencode = "d4:infod4:name4:spamee".try_into().unwrap();
+    /// let name = torrent.get("info").and_then(|info| info.get("name"));
+    /// assert_eq!(name, Some(&Bencode::ByteString(b"spam".to_vec())));
+    /// ```
+    pub fn get(&self, key: &str) -> Option<&Bencode> {
+        match self {
+            Bencode::Dictionary(map) => map.get(key),
+            Bencode::DictionaryBytes(map) => map.get(key.as_bytes()),
+            _ => None,
+        }
     }
 
-    #[test]
-    fn test_parse_dict() {
-        let tokens = vec![
-            Token::Dictionary,
+    /// Returns value stored under given key of the dictionary, like
+    /// [`Bencode::get`] does, but for key which may not be valid UTF-8.
+    fn get_bytes(&self, key: &[u8]) -> Option<&Bencode> {
+        match self {
+            Bencode::Dictionary(map) => map.get(str::from_utf8(key).ok()?),
+            Bencode::DictionaryBytes(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// Checks whether `Bencode` is a dictionary, with or without
+    /// keys which are not valid UTF-8.
+    pub(crate) fn is_dictionary(&self) -> bool {
+        matches!(self, Bencode::Dictionary(_) | Bencode::DictionaryBytes(_))
+    }
+
+    /// Checks whether `Bencode` is a dictionary containing given key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let dict: Bencode = "d3:fooi42ee".try_into().unwrap();
+    /// assert!(dict.contains_key("foo"));
+    /// assert!(!dict.contains_key("bar"));
+    /// ```
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns iterator over keys of the dictionary, in arbitrary order.
+    /// Returns `None` if `Bencode` is not a dictionary, or if it is
+    /// `Bencode::DictionaryBytes`, whose keys are not strings. Keys of the
+    /// latter are available through [`Bencode::as_dictionary_bytes`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let dict: Bencode = "d3:fooi42ee".try_into().unwrap();
+    /// assert_eq!(dict.keys().unwrap().collect::<Vec<&String>>(), vec!["foo"]);
+    /// assert!(Bencode::Integer(42).keys().is_none());
+    /// ```
+    pub fn keys(&self) -> Option<impl Iterator<Item = &String>> {
+        self.as_dictionary().map(HashMap::keys)
+    }
+
+    /// Returns list element at given index, or `None` if index is
+    /// out of range or `Bencode` is not a list.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let list = Bencode::List(vec![Bencode::Integer(1), Bencode::Integer(2)]);
+    /// assert_eq!(list.get_index(1), Some(&Bencode::Integer(2)));
+    /// assert_eq!(list.get_index(2), None);
+    /// ```
+    pub fn get_index(&self, i: usize) -> Option<&Bencode> {
+        match self {
+            Bencode::List(vec) => vec.get(i),
+            _ => None,
+        }
+    }
+
+    /// Checks whether `Bencode` is a list containing byte string equal to `needle`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let list = Bencode::List(vec![Bencode::ByteString("spam".into())]);
+    /// assert!(list.list_contains_str("spam"));
+    /// assert!(!list.list_contains_str("eggs"));
+    /// ```
+    pub fn list_contains_str(&self, needle: &str) -> bool {
+        match self {
+            Bencode::List(vec) => vec.iter().any(|elem| match elem {
+                Bencode::ByteString(s) => s == needle.as_bytes(),
+                _ => false,
+            }),
+            _ => false,
+        }
+    }
+
+    /// Returns value of the integer, or `None` if `Bencode` is not an integer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// assert_eq!(Bencode::Integer(42).as_integer(), Some(42));
+    /// assert_eq!(Bencode::List(Vec::new()).as_integer(), None);
+    /// ```
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Bencode::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns mutable reference to the integer, or `None`
+    /// if `Bencode` is not an integer.
+    pub fn as_integer_mut(&mut self) -> Option<&mut i64> {
+        match self {
+            Bencode::Integer(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns content of the byte string, or `None`
+    /// if `Bencode` is not a byte string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let value = Bencode::ByteString(vec![0xff, 0x00]);
+    /// assert_eq!(value.as_bytes(), Some([0xff, 0x00].as_ref()));
+    /// ```
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Bencode::ByteString(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns mutable reference to content of the byte string,
+    /// or `None` if `Bencode` is not a byte string.
+    pub fn as_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
+        match self {
+            Bencode::ByteString(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns content of the byte string interpreted as UTF-8, or `None`
+    /// if `Bencode` is not a byte string or its content is not valid UTF-8.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// assert_eq!(Bencode::ByteString("spam".into()).as_byte_string(), Some("spam"));
+    /// assert_eq!(Bencode::ByteString(vec![0xff]).as_byte_string(), None);
+    /// ```
+    pub fn as_byte_string(&self) -> Option<&str> {
+        str::from_utf8(self.as_bytes()?).ok()
+    }
+
+    /// Returns elements of the list, or `None` if `Bencode` is not a list.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let list = Bencode::List(vec![Bencode::Integer(1)]);
+    /// assert_eq!(list.as_list(), Some([Bencode::Integer(1)].as_ref()));
+    /// ```
+    pub fn as_list(&self) -> Option<&[Bencode]> {
+        match self {
+            Bencode::List(vec) => Some(vec),
+            _ => None,
+        }
+    }
+
+    /// Returns iterator over elements of the list. Iterator is empty
+    /// if `Bencode` is not a list.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let list: Bencode = "li1ei2ee".try_into().unwrap();
+    /// assert_eq!(list.iter().count(), 2);
+    /// assert_eq!(Bencode::Integer(1).iter().count(), 0);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = &Bencode> {
+        self.as_list().unwrap_or(&[]).iter()
+    }
+
+    /// Returns mutable reference to the list, or `None` if `Bencode` is not a list.
+    pub fn as_list_mut(&mut self) -> Option<&mut Vec<Bencode>> {
+        match self {
+            Bencode::List(vec) => Some(vec),
+            _ => None,
+        }
+    }
+
+    /// Returns entries of the dictionary, or `None` if `Bencode` is not
+    /// a dictionary. Returns `None` for `Bencode::DictionaryBytes` too,
+    /// as its keys are not strings, see [`Bencode::as_dictionary_bytes`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let dict: Bencode = "d3:fooi42ee".try_into().unwrap();
+    /// assert_eq!(dict.as_dictionary().unwrap()["foo"], Bencode::Integer(42));
+    /// ```
+    pub fn as_dictionary(&self) -> Option<&HashMap<String, Bencode>> {
+        match self {
+            Bencode::Dictionary(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Returns mutable reference to the dictionary, or `None` if `Bencode`
+    /// is not a dictionary or it is `Bencode::DictionaryBytes`.
+    pub fn as_dictionary_mut(&mut self) -> Option<&mut HashMap<String, Bencode>> {
+        match self {
+            Bencode::Dictionary(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Returns entries of the dictionary with keys which are not valid UTF-8,
+    /// or `None` if `Bencode` is not `Bencode::DictionaryBytes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let dict: Bencode = b"d1:\xffi42ee".as_ref().try_into().unwrap();
+    /// assert_eq!(dict.as_dictionary_bytes().unwrap()[b"\xff".as_ref()], Bencode::Integer(42));
+    /// assert!(dict.as_dictionary().is_none());
+    /// ```
+    pub fn as_dictionary_bytes(&self) -> Option<&HashMap<Vec<u8>, Bencode>> {
+        match self {
+            Bencode::DictionaryBytes(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Returns mutable reference to the dictionary with keys which are not
+    /// valid UTF-8, or `None` if `Bencode` is not `Bencode::DictionaryBytes`.
+    pub fn as_dictionary_bytes_mut(&mut self) -> Option<&mut HashMap<Vec<u8>, Bencode>> {
+        match self {
+            Bencode::DictionaryBytes(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Returns integer converted into the chosen numeric type, or `None` if
+    /// `Bencode` is not an integer or its value does not fit into `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// assert_eq!(Bencode::Integer(6881).as_number::<u16>(), Some(6881));
+    /// assert_eq!(Bencode::Integer(-1).as_number::<u16>(), None);
+    /// ```
+    pub fn as_number<T: TryFrom<i64>>(&self) -> Option<T> {
+        match self {
+            Bencode::Integer(n) => T::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns port number stored under given key of the dictionary. Returns
+    /// `None` if `Bencode` is not a dictionary, the key is absent, or its
+    /// value is not an integer in range of `u16`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let config: Bencode = "d4:porti6881ee".try_into().unwrap();
+    /// assert_eq!(config.port("port"), Some(6881));
+    /// ```
+    pub fn port(&self, key: &str) -> Option<u16> {
+        self.get(key)?.as_number()
+    }
+
+    /// Returns dictionaries of the list stored under given key of the
+    /// dictionary. Returns `None` if `Bencode` is not a dictionary, the key
+    /// is absent, its value is not a list, or any element is not a dictionary
+    /// with string keys, as [`Bencode::as_dictionary`] returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let info: Bencode = "d5:filesld6:lengthi1eed6:lengthi2eeee".try_into().unwrap();
+    /// assert_eq!(info.get_list_dict("files").map(|files| files.len()), Some(2));
+    /// assert_eq!(info.get_list_dict("length"), None);
+    /// ```
+    pub fn get_list_dict(&self, key: &str) -> Option<Vec<&HashMap<String, Bencode>>> {
+        self.get(key)?
+            .as_list()?
+            .iter()
+            .map(Bencode::as_dictionary)
+            .collect()
+    }
+
+    /// Consumes `Bencode` and returns owned string, if it is a byte string
+    /// containing valid UTF-8. Otherwise returns `Bencode` back, unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// assert_eq!(Bencode::ByteString("spam".into()).into_string(), Ok("spam".into()));
+    /// assert_eq!(Bencode::Integer(1).into_string(), Err(Bencode::Integer(1)));
+    /// ```
+    pub fn into_string(mut self) -> Result<String, Bencode> {
+        match &mut self {
+            Bencode::ByteString(bytes) => String::from_utf8(mem::take(bytes))
+                .map_err(|err| Bencode::ByteString(err.into_bytes())),
+            _ => Err(self),
+        }
+    }
+
+    /// Returns number of elements of the list, entries of the dictionary
+    /// or bytes of the byte string. Returns `None` for integers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let list: Bencode = "li1ei2ee".try_into().unwrap();
+    /// assert_eq!(list.len(), Some(2));
+    /// assert_eq!(Bencode::Integer(1).len(), None);
+    /// ```
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            Bencode::Integer(_) => None,
+            Bencode::ByteString(s) => Some(s.len()),
+            Bencode::List(vec) => Some(vec.len()),
+            Bencode::Dictionary(map) => Some(map.len()),
+            Bencode::DictionaryBytes(map) => Some(map.len()),
+        }
+    }
+
+    /// Checks whether list, dictionary or byte string is empty,
+    /// like [`Bencode::len`] does. Returns `None` for integers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// assert_eq!(Bencode::List(Vec::new()).is_empty(), Some(true));
+    /// assert_eq!(Bencode::Integer(0).is_empty(), None);
+    /// ```
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Checks whether `Bencode` is an empty list or an empty dictionary.
+    /// Integers and byte strings are never empty trees.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// assert!(Bencode::List(Vec::new()).is_empty_tree());
+    /// assert!(!Bencode::ByteString(Vec::new()).is_empty_tree());
+    /// ```
+    pub fn is_empty_tree(&self) -> bool {
+        match self {
+            Bencode::List(vec) => vec.is_empty(),
+            Bencode::Dictionary(map) => map.is_empty(),
+            Bencode::DictionaryBytes(map) => map.is_empty(),
+            Bencode::Integer(_) | Bencode::ByteString(_) => false,
+        }
+    }
+
+    /// Replaces `Bencode` with given value, returning the previous one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let mut value = Bencode::Integer(1);
+    /// assert_eq!(value.replace(Bencode::Integer(2)), Bencode::Integer(1));
+    /// assert_eq!(value, Bencode::Integer(2));
+    /// ```
+    pub fn replace(&mut self, new: Bencode) -> Bencode {
+        mem::replace(self, new)
+    }
+
+    /// Removes all elements from list or dictionary, leaving it empty.
+    /// Integers and byte strings are left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bensor::Bencode;
+    ///
+    /// let mut list = Bencode::List(vec![Bencode::Integer(1)]);
+    /// list.clear();
+    /// assert_eq!(list, Bencode::List(Vec::new()));
+    /// ```
+    pub fn clear(&mut self) {
+        match self {
+            Bencode::List(vec) => vec.clear(),
+            Bencode::Dictionary(map) => map.clear(),
+            Bencode::DictionaryBytes(map) => map.clear(),
+            Bencode::Integer(_) | Bencode::ByteString(_) => {}
+        }
+    }
+
+    /// Returns iterator over integers and byte strings found in `Bencode`, in
+    /// pre-order. Lists and dictionaries are not yielded, but descended into;
+    /// dictionary values are visited in order of their sorted keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let value: Bencode = "li1eli2eei3ee".try_into().unwrap();
+    /// let leaves = value.flat_iter().cloned().collect::<Vec<Bencode>>();
+    /// assert_eq!(leaves, vec![Bencode::Integer(1), Bencode::Integer(2), Bencode::Integer(3)]);
+    /// ```
+    pub fn flat_iter(&self) -> impl Iterator<Item = &Bencode> {
+        FlatIter { stack: vec![self] }
+    }
+
+    /// Returns byte strings found in `Bencode` which are longer than
+    /// `threshold` bytes, in order of [`Bencode::flat_iter`]. Dictionary
+    /// keys are not taken into account.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let value: Bencode = "l1:a4:spamd3:foo3:baree".try_into().unwrap();
+    /// assert_eq!(value.large_byte_strings(2), vec![b"spam".as_ref(), b"bar".as_ref()]);
+    /// ```
+    pub fn large_byte_strings(&self, threshold: usize) -> Vec<&[u8]> {
+        self.flat_iter()
+            .filter_map(Bencode::as_bytes)
+            .filter(|bytes| bytes.len() > threshold)
+            .collect()
+    }
+
+    /// Returns numbers of positive, zero and negative integers
+    /// found in `Bencode`, in that order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let value: Bencode = "li1ei0eli-1ei2eee".try_into().unwrap();
+    /// assert_eq!(value.integer_sign_counts(), (2, 1, 1));
+    /// ```
+    pub fn integer_sign_counts(&self) -> (usize, usize, usize) {
+        self.flat_iter()
+            .filter_map(Bencode::as_integer)
+            .fold((0, 0, 0), |(pos, zero, neg), n| match n.signum() {
+                1 => (pos + 1, zero, neg),
+                0 => (pos, zero + 1, neg),
+                _ => (pos, zero, neg + 1),
+            })
+    }
+
+    /// Clones dictionary into `BTreeMap`, which iterates over its keys in
+    /// sorted order. Returns `None` if `Bencode` is not a dictionary. Keys
+    /// which are not valid UTF-8 are converted lossily, so distinct keys
+    /// may collapse into one, keeping the value of the greatest of them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let value: Bencode = "d3:bari1e3:fooi2ee".try_into().unwrap();
+    /// let tree = value.to_btree().unwrap();
+    /// assert_eq!(tree.keys().collect::<Vec<&String>>(), vec!["bar", "foo"]);
+    /// ```
+    pub fn to_btree(&self) -> Option<BTreeMap<String, Bencode>> {
+        match self {
+            Bencode::Dictionary(map) => Some(
+                map.iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect(),
+            ),
+            Bencode::DictionaryBytes(_) => Some(
+                self.sorted_entries()?
+                    .into_iter()
+                    .map(|(key, value)| (String::from_utf8_lossy(key).into_owned(), value.clone()))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Compares integers stored under the same keys of two dictionaries and
+    /// returns sorted keys whose values differ, along with both values. Keys
+    /// absent in either dictionary or holding other values are skipped.
+    /// Keys which are not valid UTF-8 are converted lossily.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let old: Bencode = "d4:porti6881e4:sizei1ee".try_into().unwrap();
+    /// let new: Bencode = "d4:porti6882e4:sizei1ee".try_into().unwrap();
+    /// assert_eq!(old.integer_diff(&new), vec![("port".into(), 6881, 6882)]);
+    /// ```
+    pub fn integer_diff(&self, other: &Bencode) -> Vec<(String, i64, i64)> {
+        let mut res = match self.sorted_entries() {
+            Some(entries) => entries
+                .into_iter()
+                .filter_map(|(key, value)| match (value, other.get_bytes(key)) {
+                    (Bencode::Integer(a), Some(Bencode::Integer(b))) if a != b => {
+                        Some((String::from_utf8_lossy(key).into_owned(), *a, *b))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        res.sort();
+        res
+    }
+
+    /// Returns short one-line description of `Bencode`, suitable for logs.
+    /// Depth of list or dictionary counts nested containers, including itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let value: Bencode = "d4:infod6:lengthi1ee4:name4:spame".try_into().unwrap();
+    /// assert_eq!(value.summary(), "dict{2 keys, depth 2}");
+    /// assert_eq!(Bencode::Integer(42).summary(), "int(42)");
+    /// ```
+    pub fn summary(&self) -> String {
+        match self {
+            Bencode::Integer(n) => format!("int({})", n),
+            Bencode::ByteString(s) => format!("str({} bytes)", s.len()),
+            Bencode::List(vec) => format!("list{{{} items, depth {}}}", vec.len(), self.depth()),
+            Bencode::Dictionary(map) => {
+                format!("dict{{{} keys, depth {}}}", map.len(), self.depth())
+            }
+            Bencode::DictionaryBytes(map) => {
+                format!("dict{{{} keys, depth {}}}", map.len(), self.depth())
+            }
+        }
+    }
+
+    /// Returns number of nested containers on the deepest path in `Bencode`.
+    fn depth(&self) -> usize {
+        let mut max_depth = 0;
+        let mut stack = vec![(self, 0)];
+        while let Some((value, depth)) = stack.pop() {
+            let depth = depth + 1;
+            match value {
+                Bencode::Integer(_) | Bencode::ByteString(_) => continue,
+                Bencode::List(vec) => stack.extend(vec.iter().map(|child| (child, depth))),
+                Bencode::Dictionary(map) => stack.extend(map.values().map(|child| (child, depth))),
+                Bencode::DictionaryBytes(map) => {
+                    stack.extend(map.values().map(|child| (child, depth)))
+                }
+            }
+            max_depth = max_depth.max(depth);
+        }
+        max_depth
+    }
+
+    /// Projects list of dictionaries into rows, which contain values
+    /// of requested columns (or `None`, if value is absent). Returns `None`
+    /// if `Bencode` is not a list or any of its elements is not a dictionary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let files: Bencode = "ld6:lengthi10eee".try_into().unwrap();
+    /// let table = files.to_table(&["length", "path"]).unwrap();
+    /// assert_eq!(table, vec![vec![Some(Bencode::Integer(10)), None]]);
+    /// ```
+    pub fn to_table(&self, columns: &[&str]) -> Option<Vec<Vec<Option<Bencode>>>> {
+        match self {
+            Bencode::List(vec) => vec
+                .iter()
+                .map(|elem| {
+                    elem.is_dictionary().then(|| {
+                        columns
+                            .iter()
+                            .map(|&column| elem.get(column).cloned())
+                            .collect()
+                    })
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+/// Wraps integer in `Bencode::Integer`.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::Bencode;
+///
+/// assert_eq!(Bencode::from(42), Bencode::Integer(42));
+/// ```
+impl From<i64> for Bencode {
+    fn from(n: i64) -> Self {
+        Bencode::Integer(n)
+    }
+}
+
+/// Wraps bytes of the string in `Bencode::ByteString`.
+///
+/// Conversion from `&str` is not provided, because `TryFrom<&str>`
+/// already parses the string as bencoded data.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::Bencode;
+///
+/// assert_eq!(Bencode::from("spam".to_string()), Bencode::ByteString(b"spam".to_vec()));
+/// ```
+impl From<String> for Bencode {
+    fn from(s: String) -> Self {
+        Bencode::ByteString(s.into_bytes())
+    }
+}
+
+/// Wraps bytes in `Bencode::ByteString`.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::Bencode;
+///
+/// assert_eq!(Bencode::from(vec![0xff, 0x00]), Bencode::ByteString(vec![0xff, 0x00]));
+/// ```
+impl From<Vec<u8>> for Bencode {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bencode::ByteString(bytes)
+    }
+}
+
+/// Wraps elements in `Bencode::List`.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::Bencode;
+///
+/// let list: Bencode = vec![Bencode::from(1), Bencode::from(2)].into();
+/// assert_eq!(list, Bencode::List(vec![Bencode::Integer(1), Bencode::Integer(2)]));
+/// ```
+impl From<Vec<Bencode>> for Bencode {
+    fn from(list: Vec<Bencode>) -> Self {
+        Bencode::List(list)
+    }
+}
+
+/// Wraps entries in `Bencode::Dictionary`.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use bensor::Bencode;
+///
+/// let mut entries = HashMap::new();
+/// entries.insert("foo".to_string(), Bencode::from(42));
+///
+/// let dict: Bencode = entries.clone().into();
+/// assert_eq!(dict, Bencode::Dictionary(entries));
+/// ```
+impl From<HashMap<String, Bencode>> for Bencode {
+    fn from(dict: HashMap<String, Bencode>) -> Self {
+        Bencode::Dictionary(dict)
+    }
+}
+
+/// Builds `Bencode::Dictionary` entry by entry.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::Bencode;
+///
+/// let dict = Bencode::dict_builder()
+///     .insert("name", "spam".to_string())
+///     .insert("length", 42)
+///     .build();
+/// assert_eq!(dict.into_bytes(), b"d6:lengthi42e4:name4:spame".to_vec());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DictBuilder {
+    entries: HashMap<String, Bencode>,
+}
+
+impl DictBuilder {
+    /// Creates builder without any entries.
+    pub fn new() -> Self {
+        DictBuilder {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Inserts value under given key, replacing the previous value
+    /// inserted under the same key.
+    pub fn insert<K: Into<String>, V: Into<Bencode>>(mut self, key: K, value: V) -> Self {
+        self.entries.insert(key.into(), value.into());
+        self
+    }
+
+    /// Returns dictionary with all inserted entries.
+    pub fn build(self) -> Bencode {
+        Bencode::Dictionary(self.entries)
+    }
+}
+
+/// Consumes list and yields its elements. Nothing is yielded
+/// if `Bencode` is not a list.
+///
+/// # Examples
+///
+/// ```
+/// use std::convert::TryInto;
+/// use bensor::Bencode;
+///
+/// let list: Bencode = "li1ei2ee".try_into().unwrap();
+/// let left = list.into_iter().collect::<Vec<Bencode>>();
+/// assert_eq!(left, vec![Bencode::Integer(1), Bencode::Integer(2)]);
+/// ```
+impl IntoIterator for Bencode {
+    type Item = Bencode;
+    type IntoIter = vec::IntoIter<Bencode>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        match &mut self {
+            Bencode::List(vec) => mem::take(vec).into_iter(),
+            _ => Vec::new().into_iter(),
+        }
+    }
+}
+
+/// Orders values of different variants as integers, byte strings, lists
+/// and dictionaries, in that order. Integers are compared numerically,
+/// byte strings lexicographically by bytes, and lists element by element.
+/// Dictionaries are compared by their entries in order of sorted keys, the
+/// same way lists of key and value pairs would be. If their entries are
+/// equal, `Bencode::Dictionary` comes before `Bencode::DictionaryBytes`.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::Bencode;
+///
+/// let mut values = vec![
+///     Bencode::List(Vec::new()),
+///     Bencode::ByteString(b"spam".to_vec()),
+///     Bencode::Integer(42),
+///     Bencode::Integer(-1),
+/// ];
+/// values.sort();
+///
+/// assert_eq!(
+///     values,
+///     vec![
+///         Bencode::Integer(-1),
+///         Bencode::Integer(42),
+///         Bencode::ByteString(b"spam".to_vec()),
+///         Bencode::List(Vec::new()),
+///     ]
+/// );
+/// ```
+impl Ord for Bencode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Bencode::Integer(a), Bencode::Integer(b)) => a.cmp(b),
+            (Bencode::ByteString(a), Bencode::ByteString(b)) => a.cmp(b),
+            (Bencode::List(a), Bencode::List(b)) => a.cmp(b),
+            _ => match (self.sorted_entries(), other.sorted_entries()) {
+                (Some(a), Some(b)) => a.cmp(&b).then_with(|| self.rank().cmp(&other.rank())),
+                _ => self.rank().cmp(&other.rank()),
+            },
+        }
+    }
+}
+
+impl PartialOrd for Bencode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Bencode {
+    /// Returns position of the variant in the order of values.
+    fn rank(&self) -> u8 {
+        match self {
+            Bencode::Integer(_) => 0,
+            Bencode::ByteString(_) => 1,
+            Bencode::List(_) => 2,
+            Bencode::Dictionary(_) => 3,
+            Bencode::DictionaryBytes(_) => 4,
+        }
+    }
+
+    /// Returns entries of the dictionary in order of sorted keys,
+    /// or `None` if `Bencode` is not a dictionary.
+    fn sorted_entries(&self) -> Option<Vec<(&[u8], &Bencode)>> {
+        let mut entries = match self {
+            Bencode::Dictionary(map) => map
+                .iter()
+                .map(|(key, value)| (key.as_bytes(), value))
+                .collect::<Vec<(&[u8], &Bencode)>>(),
+            Bencode::DictionaryBytes(map) => map
+                .iter()
+                .map(|(key, value)| (key.as_slice(), value))
+                .collect(),
+            _ => return None,
+        };
+        entries.sort_by_key(|(key, _)| *key);
+        Some(entries)
+    }
+}
+
+/// Renders `Bencode` as indented tree. Byte strings containing valid UTF-8
+/// are quoted, other ones are shown as hexadecimal digits between angle
+/// brackets. Dictionary entries are rendered in order of sorted keys.
+///
+/// # Examples
+///
+/// ```
+/// use std::convert::TryInto;
+/// use bensor::Bencode;
+///
+/// let value: Bencode = b"d3:bar2:\xff\x013:fooli1eee".as_ref().try_into().unwrap();
+/// let right = "{\n  \"bar\": <ff01>,\n  \"foo\": [\n    1\n  ]\n}";
+/// assert_eq!(value.to_string(), right);
+/// ```
+impl fmt::Display for Bencode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+const DISPLAY_INDENT: usize = 2;
+
+impl Bencode {
+    /// Exports `Bencode` as indented JSON. Integers become numbers, lists
+    /// become arrays and dictionaries become objects, with keys in sorted
+    /// order. Byte strings containing valid UTF-8 become strings, other ones
+    /// become strings of hexadecimal digits prefixed with `hex:` marker.
+    /// Exported data cannot be parsed back into `Bencode`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let value: Bencode = b"d3:bar2:\xff\x013:fooli1eee".as_ref().try_into().unwrap();
+    /// let right = "{\n  \"bar\": \"hex:ff01\",\n  \"foo\": [\n    1\n  ]\n}";
+    /// assert_eq!(value.to_json_string(), right);
+    /// ```
+    pub fn to_json_string(&self) -> String {
+        let mut res = String::with_capacity(self.encoded_len());
+        self.json_indented(&mut res, 0);
+        res
+    }
+
+    fn json_indented(&self, res: &mut String, indent: usize) {
+        let inner = indent + DISPLAY_INDENT;
+        match self {
+            Bencode::Integer(n) => res.push_str(&n.to_string()),
+            Bencode::ByteString(bytes) => json_bytes(res, bytes),
+            Bencode::List(vec) if vec.is_empty() => res.push_str("[]"),
+            Bencode::List(vec) => {
+                res.push('[');
+                for (i, elem) in vec.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    res.push_str(&format!("{}\n{:indent$}", separator, "", indent = inner));
+                    elem.json_indented(res, inner);
+                }
+                res.push_str(&format!("\n{:indent$}]", "", indent = indent));
+            }
+            Bencode::Dictionary(map) => {
+                let entries = map.iter().map(|(key, value)| (key.as_bytes(), value));
+                json_entries(res, entries.collect(), indent)
+            }
+            Bencode::DictionaryBytes(map) => {
+                let entries = map.iter().map(|(key, value)| (key.as_slice(), value));
+                json_entries(res, entries.collect(), indent)
+            }
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let inner = indent + DISPLAY_INDENT;
+        match self {
+            Bencode::Integer(n) => write!(f, "{}", n),
+            Bencode::ByteString(bytes) => fmt_bytes(f, bytes),
+            Bencode::List(vec) if vec.is_empty() => f.write_str("[]"),
+            Bencode::List(vec) => {
+                f.write_str("[")?;
+                for (i, elem) in vec.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(f, "{}\n{:indent$}", separator, "", indent = inner)?;
+                    elem.fmt_indented(f, inner)?;
+                }
+                write!(f, "\n{:indent$}]", "", indent = indent)
+            }
+            Bencode::Dictionary(map) => {
+                let entries = map.iter().map(|(key, value)| (key.as_bytes(), value));
+                fmt_entries(f, entries.collect(), indent)
+            }
+            Bencode::DictionaryBytes(map) => {
+                let entries = map.iter().map(|(key, value)| (key.as_slice(), value));
+                fmt_entries(f, entries.collect(), indent)
+            }
+        }
+    }
+}
+
+/// Renders byte string quoted if it contains valid UTF-8,
+/// or as hexadecimal digits between angle brackets otherwise.
+fn fmt_bytes(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    match str::from_utf8(bytes) {
+        Ok(string) => write!(f, "{:?}", string),
+        Err(_) => {
+            f.write_str("<")?;
+            bytes
+                .iter()
+                .try_for_each(|byte| write!(f, "{:02x}", byte))?;
+            f.write_str(">")
+        }
+    }
+}
+
+/// Renders dictionary entries in order of sorted keys.
+fn fmt_entries(
+    f: &mut fmt::Formatter<'_>,
+    mut entries: Vec<(&[u8], &Bencode)>,
+    indent: usize,
+) -> fmt::Result {
+    if entries.is_empty() {
+        return f.write_str("{}");
+    }
+    entries.sort_by_key(|(key, _)| *key);
+
+    let inner = indent + DISPLAY_INDENT;
+    f.write_str("{")?;
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        write!(f, "{}\n{:indent$}", separator, "", indent = inner)?;
+        fmt_bytes(f, key)?;
+        f.write_str(": ")?;
+        value.fmt_indented(f, inner)?;
+    }
+    write!(f, "\n{:indent$}}}", "", indent = indent)
+}
+
+/// Exports byte string as JSON string, escaping it if it contains
+/// valid UTF-8, or as hexadecimal digits with `hex:` marker otherwise.
+fn json_bytes(res: &mut String, bytes: &[u8]) {
+    res.push('"');
+    match str::from_utf8(bytes) {
+        Ok(string) => {
+            for c in string.chars() {
+                match c {
+                    '"' => res.push_str("\\\""),
+                    '\\' => res.push_str("\\\\"),
+                    '\n' => res.push_str("\\n"),
+                    '\r' => res.push_str("\\r"),
+                    '\t' => res.push_str("\\t"),
+                    c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => res.push(c),
+                }
+            }
+        }
+        Err(_) => {
+            res.push_str("hex:");
+            bytes
+                .iter()
+                .for_each(|byte| res.push_str(&format!("{:02x}", byte)));
+        }
+    }
+    res.push('"');
+}
+
+/// Exports dictionary entries as JSON object, in order of sorted keys.
+fn json_entries(res: &mut String, mut entries: Vec<(&[u8], &Bencode)>, indent: usize) {
+    if entries.is_empty() {
+        return res.push_str("{}");
+    }
+    entries.sort_by_key(|(key, _)| *key);
+
+    let inner = indent + DISPLAY_INDENT;
+    res.push('{');
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        res.push_str(&format!("{}\n{:indent$}", separator, "", indent = inner));
+        json_bytes(res, key);
+        res.push_str(": ");
+        value.json_indented(res, inner);
+    }
+    res.push_str(&format!("\n{:indent$}}}", "", indent = indent));
+}
+
+/// Iterator returned by [`Bencode::flat_iter`].
+struct FlatIter<'a> {
+    stack: Vec<&'a Bencode>,
+}
+
+impl<'a> Iterator for FlatIter<'a> {
+    type Item = &'a Bencode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(value) = self.stack.pop() {
+            match value {
+                Bencode::List(vec) => self.stack.extend(vec.iter().rev()),
+                Bencode::Dictionary(map) => {
+                    let mut entries = map.iter().collect::<Vec<(&String, &Bencode)>>();
+                    entries.sort_by_key(|(key, _)| *key);
+                    self.stack
+                        .extend(entries.into_iter().rev().map(|(_, value)| value));
+                }
+                Bencode::DictionaryBytes(map) => {
+                    let mut entries = map.iter().collect::<Vec<(&Vec<u8>, &Bencode)>>();
+                    entries.sort_by_key(|(key, _)| *key);
+                    self.stack
+                        .extend(entries.into_iter().rev().map(|(_, value)| value));
+                }
+                leaf => return Some(leaf),
+            }
+        }
+        None
+    }
+}
+
+/// Represents possible complications that can occur during parsing tokenized data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Empty token container provided.
+    NoTokens,
+    /// There is end token without need.
+    InvalidEndToken,
+    /// There is the list without explicit end token.
+    NoEndList,
+    /// There is a attempt to use type other than ByteString
+    /// as key in the dictionary.
+    InvalidDictionaryKey,
+    /// There is the dictionary without explicit end token.
+    NoEndDictionary,
+    /// Transformation of dictionary keys produced the same key twice.
+    KeyCollision(String),
+    /// Key requested in the order is not present in the dictionary.
+    UnknownKey(String),
+    /// Dictionary key is not present in the requested order.
+    MissingKey(String),
+    /// Lists and dictionaries are nested deeper than allowed.
+    DepthLimitExceeded,
+    /// Byte string is not valid UTF-8 past given offset.
+    InvalidUtf8 { valid_up_to: usize },
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoTokens => write!(f, "There are no tokens in the given vector."),
+            Error::InvalidEndToken => write!(f, "Too many end characters in given data."),
+            Error::NoEndList => write!(f, "There is list without end character in given data."),
+            Error::InvalidDictionaryKey => {
+                write!(f, "Dictionaries can only have byte strings as keys.")
+            }
+            Error::NoEndDictionary => write!(
+                f,
+                "There is dictionary without end character in given data."
+            ),
+            Error::KeyCollision(key) => write!(
+                f,
+                "Transformed dictionary contains key \"{}\" more than once.",
+                key
+            ),
+            Error::UnknownKey(key) => {
+                write!(f, "Dictionary does not contain requested key \"{}\".", key)
+            }
+            Error::MissingKey(key) => write!(
+                f,
+                "Dictionary key \"{}\" is absent from the requested order.",
+                key
+            ),
+            Error::DepthLimitExceeded => {
+                write!(f, "Lists and dictionaries are nested deeper than allowed.")
+            }
+            Error::InvalidUtf8 { valid_up_to } => write!(
+                f,
+                "Byte string is not valid UTF-8 past offset {}.",
+                valid_up_to
+            ),
+        }
+    }
+}
+
+pub(crate) fn parse(tokens: Vec<Token>) -> Result<Bencode, Error> {
+    parse_first(tokens).map(|(value, _)| value)
+}
+
+/// Parses the first value from given tokens. Returns it along
+/// with the number of tokens it was built from.
+pub(crate) fn parse_first(tokens: Vec<Token>) -> Result<(Bencode, usize), Error> {
+    parse_first_with_depth(tokens, DEFAULT_MAX_DEPTH).map(|(value, consumed, _)| (value, consumed))
+}
+
+/// Maximum nesting depth of lists and dictionaries accepted by default.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Part of the value which is still to be encoded by `Bencode::encode_into`.
+enum Encoding {
+    Value(Bencode),
+    Key(Vec<u8>),
+    End,
+}
+
+/// Container which is being built while parsing.
+enum Frame {
+    List(Vec<Bencode>),
+    /// Entries built so far, along with the key that waits for its value.
+    Dictionary(HashMap<Vec<u8>, Bencode>, Option<Vec<u8>>),
+}
+
+/// Parses the first value from given tokens like `parse_first` does,
+/// but fails if lists and dictionaries are nested deeper than `max_depth`.
+/// Also returns depth and number of values counted along the way.
+///
+/// Unfinished containers are kept on explicit stack instead of the call
+/// stack, so arbitrarily deep nesting can be parsed if the limit allows it.
+pub(crate) fn parse_first_with_depth(
+    tokens: Vec<Token>,
+    max_depth: usize,
+) -> Result<(Bencode, usize, ParseStats), Error> {
+    // Tokens cannot make up more than `usize::MAX` values.
+    parse_first_bounded(tokens, max_depth, usize::MAX)
+        .map(|parsed| parsed.expect("number of values exceeds usize::MAX"))
+}
+
+/// Parses the first value from given tokens like `parse_first_with_depth`
+/// does, unless it is made of more than `max_values` values, in which case
+/// `None` is returned as soon as the limit is exceeded.
+pub(crate) fn parse_first_bounded(
+    tokens: Vec<Token>,
+    max_depth: usize,
+    max_values: usize,
+) -> Result<Option<(Bencode, usize, ParseStats)>, Error> {
+    let total = tokens.len();
+    let mut tokens = tokens.into_iter();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut stats = ParseStats::default();
+    loop {
+        let token = match (tokens.next(), stack.last()) {
+            (Some(token), _) => token,
+            (None, None) => return Err(Error::NoTokens),
+            (None, Some(Frame::List(_))) => return Err(Error::NoEndList),
+            (None, Some(Frame::Dictionary(..))) => return Err(Error::NoEndDictionary),
+        };
+
+        let depth = stack.len();
+        let value = match (stack.last_mut(), token) {
+            (Some(Frame::Dictionary(_, key @ None)), Token::ByteString(new_key)) => {
+                *key = Some(new_key);
+                continue;
+            }
+            (Some(Frame::Dictionary(entries, None)), Token::End) => {
+                let entries = mem::take(entries);
+                stack.pop();
+                dictionary(entries)
+            }
+            (Some(Frame::Dictionary(_, None)), _) => return Err(Error::InvalidDictionaryKey),
+            (_, Token::List) | (_, Token::Dictionary) if depth >= max_depth => {
+                return Err(Error::DepthLimitExceeded)
+            }
+            (_, Token::List) => {
+                stack.push(Frame::List(Vec::new()));
+                stats.max_depth = stats.max_depth.max(stack.len());
+                continue;
+            }
+            (_, Token::Dictionary) => {
+                stack.push(Frame::Dictionary(HashMap::new(), None));
+                stats.max_depth = stats.max_depth.max(stack.len());
+                continue;
+            }
+            (Some(Frame::List(list)), Token::End) => {
+                let list = mem::take(list);
+                stack.pop();
+                Bencode::List(list)
+            }
+            (_, Token::End) => return Err(Error::InvalidEndToken),
+            (_, Token::Integer(val)) => Bencode::Integer(val),
+            (_, Token::ByteString(val)) => Bencode::ByteString(val),
+        };
+        if stats.node_count == max_values {
+            return Ok(None);
+        }
+        stats.node_count += 1;
+
+        match stack.last_mut() {
+            None => return Ok(Some((value, total - tokens.len(), stats))),
+            Some(Frame::List(list)) => list.push(value),
+            Some(Frame::Dictionary(entries, key)) => {
+                // Dictionary waiting for key has been handled above.
+                if let Some(key) = key.take() {
+                    entries.insert(key, value);
+                }
+            }
+        }
+    }
+}
+
+/// Builds `Bencode::Dictionary` from given entries, or
+/// `Bencode::DictionaryBytes` if any of their keys is not valid UTF-8.
+pub(crate) fn dictionary(entries: HashMap<Vec<u8>, Bencode>) -> Bencode {
+    if entries.keys().all(|key| str::from_utf8(key).is_ok()) {
+        let entries = entries.into_iter().filter_map(|(key, value)| {
+            // Every key has been checked already.
+            String::from_utf8(key).ok().map(|key| (key, value))
+        });
+        Bencode::Dictionary(entries.collect())
+    } else {
+        Bencode::DictionaryBytes(entries)
+    }
+}
+
+/// Parses tokens like `parse` does, but instead of failing on the first
+/// complication, returns value built from the tokens preceding it.
+/// Unfinished lists and dictionaries are closed. Nesting deeper than
+/// `DEFAULT_MAX_DEPTH` is a complication too.
+pub(crate) fn parse_recover(tokens: Vec<Token>) -> (Option<Bencode>, Option<Error>) {
+    let mut tokens = tokens.into_iter();
+    let mut stack: Vec<Frame> = Vec::new();
+    let err = loop {
+        let token = match (tokens.next(), stack.last()) {
+            (Some(token), _) => token,
+            (None, None) => return (None, Some(Error::NoTokens)),
+            (None, Some(Frame::List(_))) => break Error::NoEndList,
+            (None, Some(Frame::Dictionary(..))) => break Error::NoEndDictionary,
+        };
+
+        let depth = stack.len();
+        let value = match (stack.last_mut(), token) {
+            (Some(Frame::Dictionary(_, key @ None)), Token::ByteString(new_key)) => {
+                *key = Some(new_key);
+                continue;
+            }
+            (Some(Frame::Dictionary(entries, None)), Token::End) => {
+                let entries = mem::take(entries);
+                stack.pop();
+                dictionary(entries)
+            }
+            (Some(Frame::Dictionary(_, None)), _) => break Error::InvalidDictionaryKey,
+            (_, Token::List) | (_, Token::Dictionary) if depth >= DEFAULT_MAX_DEPTH => {
+                break Error::DepthLimitExceeded
+            }
+            (_, Token::List) => {
+                stack.push(Frame::List(Vec::new()));
+                continue;
+            }
+            (_, Token::Dictionary) => {
+                stack.push(Frame::Dictionary(HashMap::new(), None));
+                continue;
+            }
+            (Some(Frame::List(list)), Token::End) => {
+                let list = mem::take(list);
+                stack.pop();
+                Bencode::List(list)
+            }
+            (_, Token::End) => break Error::InvalidEndToken,
+            (_, Token::Integer(val)) => Bencode::Integer(val),
+            (_, Token::ByteString(val)) => Bencode::ByteString(val),
+        };
+
+        match stack.last_mut() {
+            None => return (Some(value), None),
+            Some(Frame::List(list)) => list.push(value),
+            Some(Frame::Dictionary(entries, key)) => {
+                // Dictionary waiting for key has been handled above.
+                if let Some(key) = key.take() {
+                    entries.insert(key, value);
+                }
+            }
+        }
+    };
+
+    // Close unfinished containers, starting with the innermost one.
+    // Key without value is dropped.
+    let mut value = None;
+    while let Some(frame) = stack.pop() {
+        value = Some(match frame {
+            Frame::List(mut list) => {
+                list.extend(value);
+                Bencode::List(list)
+            }
+            Frame::Dictionary(mut entries, key) => {
+                if let (Some(key), Some(value)) = (key, value) {
+                    entries.insert(key, value);
+                }
+                dictionary(entries)
+            }
+        });
+    }
+    (value, Some(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument;
+    use crate::lexer::Token;
+
+    #[test]
+    fn test_parse_list() {
+        let tokens = vec![
+            Token::List,
+            Token::Integer(55),
+            Token::ByteString("str".into()),
+            Token::End,
+        ];
+        let left = parse(tokens).unwrap();
+        let right = Bencode::List(vec![
+            Bencode::Integer(55),
+            Bencode::ByteString("str".into()),
+        ]);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_parse_nested_list() {
+        let tokens = vec![
+            Token::List,
+            Token::Integer(55),
+            Token::List,
+            Token::ByteString("str".into()),
+            Token::End,
+            Token::End,
+        ];
+        let left = parse(tokens).unwrap();
+        let right = Bencode::List(vec![
+            Bencode::Integer(55),
+            Bencode::List(vec![Bencode::ByteString("str".into())]),
+        ]);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_parse_dict() {
+        let tokens = vec![
+            Token::Dictionary,
             Token::ByteString("bar".into()),
             Token::ByteString("spam".into()),
             Token::ByteString("foo".into()),
@@ -241,112 +2001,1163 @@ mod tests {
         ];
         let left = parse(tokens).unwrap();
 
-        let dict = {
-            let mut dict = HashMap::new();
-            dict.insert("bar".into(), Bencode::ByteString("spam".into()));
-            dict.insert("foo".into(), Bencode::Integer(42));
-            dict
+        let dict = {
+            let mut dict = HashMap::new();
+            dict.insert("bar".into(), Bencode::ByteString("spam".into()));
+            dict.insert("foo".into(), Bencode::Integer(42));
+            dict
+        };
+        let right = Bencode::Dictionary(dict);
+
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_parse_nested_dict() {
+        let tokens = vec![
+            Token::Dictionary,
+            Token::ByteString("bar".into()),
+            Token::ByteString("spam".into()),
+            Token::ByteString("foo".into()),
+            Token::Dictionary,
+            Token::ByteString("nested".into()),
+            Token::Integer(-123),
+            Token::End,
+            Token::End,
+        ];
+        let left = parse(tokens).unwrap();
+
+        let dict = {
+            let mut dict = HashMap::new();
+            dict.insert("bar".into(), Bencode::ByteString("spam".into()));
+
+            let mut nested_dict = HashMap::new();
+            nested_dict.insert("nested".into(), Bencode::Integer(-123));
+
+            dict.insert("foo".into(), Bencode::Dictionary(nested_dict));
+            dict
+        };
+
+        let right = Bencode::Dictionary(dict);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_list_into_bytes() {
+        let left = Bencode::List(vec![
+            Bencode::Integer(2137),
+            Bencode::ByteString("Hello World!".into()),
+            Bencode::Integer(2020),
+        ])
+        .into_bytes();
+
+        let right = b"li2137e12:Hello World!i2020ee".to_vec();
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_dict_into_bytes() {
+        let left = {
+            let mut h = HashMap::new();
+            h.insert("current_year".into(), Bencode::Integer(2020));
+            h.insert("power_level".into(), Bencode::Integer(9001));
+            h.insert(
+                "some_random_bytes".into(),
+                Bencode::ByteString("welcome_my_dear_bytes".into()),
+            );
+            h.insert(
+                "integer_list".into(),
+                Bencode::List(vec![
+                    Bencode::Integer(5),
+                    Bencode::Integer(10),
+                    Bencode::Integer(100),
+                ]),
+            );
+            Bencode::Dictionary(h)
+        }
+        .into_bytes();
+
+        let right = b"d12:current_yeari2020e12:integer_listli5ei10ei100ee11:power_leveli9001e17:some_random_bytes21:welcome_my_dear_bytese".to_vec();
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_nested_dict_into_bytes() {
+        let left = {
+            let mut h = HashMap::new();
+            let nested_dict = {
+                let mut h = HashMap::new();
+                h.insert("abc".into(), Bencode::Integer(123));
+                h.insert("def".into(), Bencode::Integer(456));
+                h
+            };
+            h.insert("nested".into(), Bencode::Dictionary(nested_dict));
+            h.insert(
+                "list".into(),
+                Bencode::List(vec![
+                    Bencode::Integer(1),
+                    Bencode::Integer(2),
+                    Bencode::Integer(1000),
+                ]),
+            );
+            Bencode::Dictionary(h)
+        }
+        .into_bytes();
+
+        let right = b"d4:listli1ei2ei1000ee6:nestedd3:abci123e3:defi456eee".to_vec();
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let mut left = {
+            let mut nested = HashMap::new();
+            nested.insert("Nested".into(), Bencode::Integer(1));
+
+            let mut h = HashMap::new();
+            h.insert(
+                "LIST".into(),
+                Bencode::List(vec![Bencode::Dictionary(nested)]),
+            );
+            h.insert("Name".into(), Bencode::ByteString("VALUE".into()));
+            Bencode::Dictionary(h)
+        };
+        left.map_keys(|key| key.to_lowercase()).unwrap();
+
+        let right = {
+            let mut nested = HashMap::new();
+            nested.insert("nested".into(), Bencode::Integer(1));
+
+            let mut h = HashMap::new();
+            h.insert(
+                "list".into(),
+                Bencode::List(vec![Bencode::Dictionary(nested)]),
+            );
+            h.insert("name".into(), Bencode::ByteString("VALUE".into()));
+            Bencode::Dictionary(h)
+        };
+
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_map_keys_collision() {
+        let mut dict = {
+            let mut h = HashMap::new();
+            h.insert("Key".into(), Bencode::Integer(1));
+            h.insert("KEY".into(), Bencode::Integer(2));
+            Bencode::Dictionary(h)
+        };
+        let before = dict.clone();
+
+        let result = dict.map_keys(|key| key.to_lowercase());
+
+        assert_eq!(result, Err(Error::KeyCollision("key".into())));
+        assert_eq!(dict, before);
+    }
+
+    #[test]
+    fn test_dict_into_bytes_is_deterministic() {
+        // Every HashMap gets its own random seed, so iteration order
+        // differs between them and only sorting keeps output stable.
+        let build = || {
+            let mut h = HashMap::new();
+            for (i, key) in ["alpha", "beta", "gamma", "delta", "epsilon", "zeta"]
+                .iter()
+                .enumerate()
+            {
+                h.insert(key.to_string(), Bencode::Integer(i as i64));
+            }
+            Bencode::Dictionary(h)
+        };
+
+        let right = build().into_bytes();
+        for _ in 0..100 {
+            assert_eq!(build().into_bytes(), right);
+        }
+    }
+
+    #[test]
+    fn test_shape_fingerprint() {
+        let build = |name: &str, length: i64| {
+            let mut h = HashMap::new();
+            h.insert("name".into(), Bencode::ByteString(name.into()));
+            h.insert("length".into(), Bencode::Integer(length));
+            h.insert(
+                "path".into(),
+                Bencode::List(vec![Bencode::ByteString(name.into())]),
+            );
+            Bencode::Dictionary(h)
+        };
+
+        let left = build("first", 1).shape_fingerprint();
+        let right = build("second", 2).shape_fingerprint();
+
+        assert_eq!(left, right);
+        assert_eq!(left, r#"d{"length":i,"name":s,"path":l[s]}"#);
+        assert_ne!(left, Bencode::Integer(1).shape_fingerprint());
+    }
+
+    #[test]
+    fn test_parse_recover() {
+        let tokens = vec![
+            Token::Dictionary,
+            Token::ByteString("foo".into()),
+            Token::List,
+            Token::Integer(1),
+            Token::Integer(2),
+        ];
+        let (left, err) = parse_recover(tokens);
+
+        let right = {
+            let mut dict = HashMap::new();
+            dict.insert(
+                "foo".into(),
+                Bencode::List(vec![Bencode::Integer(1), Bencode::Integer(2)]),
+            );
+            Bencode::Dictionary(dict)
+        };
+
+        assert_eq!(left, Some(right));
+        assert_eq!(err, Some(Error::NoEndList));
+    }
+
+    #[test]
+    fn test_parse_recover_deeply_nested() {
+        let tokens = vec![Token::List; 50_000];
+        let (left, err) = parse_recover(tokens);
+
+        assert_eq!(left.map(|value| value.depth()), Some(DEFAULT_MAX_DEPTH));
+        assert_eq!(err, Some(Error::DepthLimitExceeded));
+    }
+
+    #[test]
+    fn test_get_index() {
+        let list = Bencode::List(vec![
+            Bencode::Integer(55),
+            Bencode::ByteString("str".into()),
+        ]);
+
+        assert_eq!(list.get_index(0), Some(&Bencode::Integer(55)));
+        assert_eq!(list.get_index(1), Some(&Bencode::ByteString("str".into())));
+        assert_eq!(list.get_index(2), None);
+        assert_eq!(Bencode::Integer(55).get_index(0), None);
+    }
+
+    #[test]
+    fn test_as_number() {
+        assert_eq!(Bencode::Integer(255).as_number::<u8>(), Some(255));
+        assert_eq!(Bencode::Integer(256).as_number::<u8>(), None);
+        assert_eq!(Bencode::Integer(-70000).as_number::<i32>(), Some(-70000));
+        assert_eq!(Bencode::ByteString("1".into()).as_number::<i32>(), None);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut list = Bencode::List(vec![Bencode::Integer(1), Bencode::Integer(2)]);
+        list.clear();
+        assert_eq!(list, Bencode::List(Vec::new()));
+
+        let mut dict = {
+            let mut h = HashMap::new();
+            h.insert("foo".into(), Bencode::Integer(42));
+            Bencode::Dictionary(h)
+        };
+        dict.clear();
+        assert_eq!(dict, Bencode::Dictionary(HashMap::new()));
+
+        let mut integer = Bencode::Integer(42);
+        integer.clear();
+        assert_eq!(integer, Bencode::Integer(42));
+    }
+
+    #[test]
+    fn test_to_table() {
+        let file = |path: &str, length: Option<i64>| {
+            let mut h = HashMap::new();
+            h.insert(
+                "path".into(),
+                Bencode::List(vec![Bencode::ByteString(path.into())]),
+            );
+            if let Some(length) = length {
+                h.insert("length".into(), Bencode::Integer(length));
+            }
+            Bencode::Dictionary(h)
+        };
+        let files = Bencode::List(vec![file("a.txt", Some(10)), file("b.txt", None)]);
+
+        let left = files.to_table(&["path", "length"]).unwrap();
+        let right = vec![
+            vec![
+                Some(Bencode::List(vec![Bencode::ByteString("a.txt".into())])),
+                Some(Bencode::Integer(10)),
+            ],
+            vec![
+                Some(Bencode::List(vec![Bencode::ByteString("b.txt".into())])),
+                None,
+            ],
+        ];
+
+        assert_eq!(left, right);
+        assert_eq!(
+            Bencode::List(vec![Bencode::Integer(1)]).to_table(&["path"]),
+            None
+        );
+        assert_eq!(Bencode::Integer(1).to_table(&["path"]), None);
+    }
+
+    #[test]
+    fn test_into_bytes_with_order() {
+        let torrent = || {
+            let mut info = HashMap::new();
+            info.insert("length".into(), Bencode::Integer(1));
+
+            let mut h = HashMap::new();
+            h.insert("announce".into(), Bencode::ByteString("udp:a".into()));
+            h.insert("info".into(), Bencode::Dictionary(info));
+            Bencode::Dictionary(h)
+        };
+
+        let left = torrent().into_bytes_with_order(&["info", "announce"]);
+        let right = b"d4:infod6:lengthi1ee8:announce5:udp:ae".to_vec();
+        assert_eq!(left, Ok(right));
+
+        assert_eq!(
+            torrent().into_bytes_with_order(&["info"]),
+            Err(Error::MissingKey("announce".into()))
+        );
+        assert_eq!(
+            torrent().into_bytes_with_order(&["info", "announce", "comment"]),
+            Err(Error::UnknownKey("comment".into()))
+        );
+    }
+
+    #[test]
+    fn test_list_contains_str() {
+        let list = Bencode::List(vec![
+            Bencode::Integer(55),
+            Bencode::ByteString("str".into()),
+        ]);
+
+        assert!(list.list_contains_str("str"));
+        assert!(!list.list_contains_str("55"));
+        assert!(!Bencode::ByteString("str".into()).list_contains_str("str"));
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let value = {
+            let mut h = HashMap::new();
+            h.insert("list".into(), Bencode::List(vec![Bencode::Integer(-10)]));
+            h.insert("name".into(), Bencode::ByteString("Hello World!".into()));
+            Bencode::Dictionary(h)
+        };
+        assert_eq!(value.encoded_len(), value.into_bytes().len());
+    }
+
+    #[test]
+    fn test_top_level_key_sizes() {
+        let value = {
+            let mut h = HashMap::new();
+            h.insert("small".into(), Bencode::Integer(1));
+            h.insert("pieces".into(), Bencode::ByteString(vec![b'x'; 100]));
+            h.insert(
+                "list".into(),
+                Bencode::List(vec![Bencode::Integer(1), Bencode::Integer(2)]),
+            );
+            Bencode::Dictionary(h)
         };
-        let right = Bencode::Dictionary(dict);
 
+        let left = value.top_level_key_sizes().unwrap();
+        let right = vec![
+            ("pieces".to_string(), 104),
+            ("list".to_string(), 8),
+            ("small".to_string(), 3),
+        ];
         assert_eq!(left, right);
+        assert_eq!(Bencode::Integer(1).top_level_key_sizes(), None);
     }
 
     #[test]
-    fn test_parse_nested_dict() {
+    fn test_parse_first() {
         let tokens = vec![
-            Token::Dictionary,
-            Token::ByteString("bar".into()),
-            Token::ByteString("spam".into()),
-            Token::ByteString("foo".into()),
-            Token::Dictionary,
-            Token::ByteString("nested".into()),
-            Token::Integer(-123),
-            Token::End,
+            Token::List,
+            Token::Integer(1),
             Token::End,
+            Token::Integer(2),
         ];
-        let left = parse(tokens).unwrap();
+        let left = parse_first(tokens).unwrap();
+        assert_eq!(left, (Bencode::List(vec![Bencode::Integer(1)]), 3));
+    }
 
-        let dict = {
-            let mut dict = HashMap::new();
-            dict.insert("bar".into(), Bencode::ByteString("spam".into()));
+    #[test]
+    fn test_is_empty_tree() {
+        assert!(parse(vec![Token::List, Token::End])
+            .unwrap()
+            .is_empty_tree());
+        assert!(parse(vec![Token::Dictionary, Token::End])
+            .unwrap()
+            .is_empty_tree());
+        assert!(!Bencode::List(vec![Bencode::Integer(1)]).is_empty_tree());
+        assert!(!Bencode::Integer(0).is_empty_tree());
+    }
 
-            let mut nested_dict = HashMap::new();
-            nested_dict.insert("nested".into(), Bencode::Integer(-123));
+    #[test]
+    fn test_into_string() {
+        let value = parse(vec![Token::ByteString("spam".into())]).unwrap();
+        assert_eq!(value.into_string(), Ok(String::from("spam")));
 
-            dict.insert("foo".into(), Bencode::Dictionary(nested_dict));
-            dict
+        let list = Bencode::List(vec![Bencode::ByteString("spam".into())]);
+        let recovered = list.clone().into_string().unwrap_err();
+        assert_eq!(recovered, list);
+    }
+
+    #[test]
+    fn test_into_bytes_with_capacity() {
+        let value = {
+            let mut h = HashMap::new();
+            h.insert("list".into(), Bencode::List(vec![Bencode::Integer(-10)]));
+            h.insert("name".into(), Bencode::ByteString("Hello World!".into()));
+            Bencode::Dictionary(h)
         };
+        let cap = value.encoded_len();
 
-        let right = Bencode::Dictionary(dict);
-        assert_eq!(left, right);
+        let bytes = value.clone().into_bytes_with_capacity(cap);
+        assert_eq!(bytes, value.into_bytes());
+        assert_eq!(bytes.len(), cap);
+        assert_eq!(bytes.capacity(), cap);
     }
 
     #[test]
-    fn test_list_into_bytes() {
-        let left = {
-            let mut v = Vec::new();
-            v.push(Bencode::Integer(2137));
-            v.push(Bencode::ByteString("Hello World!".into()));
-            v.push(Bencode::Integer(2020));
-            Bencode::List(v)
-        }
-        .into_bytes();
-
-        let right = b"li2137e12:Hello World!i2020ee".to_vec();
+    fn test_flat_iter() {
+        let value = {
+            let mut h = HashMap::new();
+            h.insert("b".into(), Bencode::List(vec![Bencode::Integer(2)]));
+            h.insert("a".into(), Bencode::ByteString("one".into()));
+            h.insert("c".into(), Bencode::Dictionary(HashMap::new()));
+            Bencode::List(vec![
+                Bencode::Integer(0),
+                Bencode::Dictionary(h),
+                Bencode::List(vec![Bencode::List(vec![Bencode::Integer(3)])]),
+            ])
+        };
+        let left = value.flat_iter().cloned().collect::<Vec<Bencode>>();
+        let right = vec![
+            Bencode::Integer(0),
+            Bencode::ByteString("one".into()),
+            Bencode::Integer(2),
+            Bencode::Integer(3),
+        ];
         assert_eq!(left, right);
+        assert_eq!(Bencode::List(Vec::new()).flat_iter().count(), 0);
     }
 
     #[test]
-    fn test_dict_into_bytes() {
-        let left = {
+    fn test_total_key_bytes() {
+        let value = {
+            let mut inner = HashMap::new();
+            inner.insert("a".repeat(10), Bencode::Integer(1));
             let mut h = HashMap::new();
-            h.insert("current_year".into(), Bencode::Integer(2020));
-            h.insert("power_level".into(), Bencode::Integer(9001));
-            h.insert(
-                "some_random_bytes".into(),
-                Bencode::ByteString("welcome_my_dear_bytes".into()),
-            );
             h.insert(
-                "integer_list".into(),
-                Bencode::List(vec![
-                    Bencode::Integer(5),
-                    Bencode::Integer(10),
-                    Bencode::Integer(100),
-                ]),
+                "list".into(),
+                Bencode::List(vec![Bencode::Dictionary(inner)]),
             );
+            h.insert("name".into(), Bencode::ByteString("Hello World!".into()));
             Bencode::Dictionary(h)
-        }
-        .into_bytes();
+        };
+        assert_eq!(value.total_key_bytes(), 6 + 6 + 13);
+        assert_eq!(
+            Bencode::List(vec![Bencode::Integer(1)]).total_key_bytes(),
+            0
+        );
+    }
 
-        let right = b"d12:current_yeari2020e12:integer_listli5ei10ei100ee11:power_leveli9001e17:some_random_bytes21:welcome_my_dear_bytese".to_vec();
+    #[test]
+    fn test_to_btree() {
+        let value = {
+            let mut h = HashMap::new();
+            h.insert("zebra".into(), Bencode::Integer(3));
+            h.insert("apple".into(), Bencode::Integer(1));
+            h.insert("mango".into(), Bencode::Integer(2));
+            Bencode::Dictionary(h)
+        };
+        let tree = value.to_btree().unwrap();
+        let left = tree.into_iter().collect::<Vec<(String, Bencode)>>();
+        let right = vec![
+            ("apple".to_string(), Bencode::Integer(1)),
+            ("mango".to_string(), Bencode::Integer(2)),
+            ("zebra".to_string(), Bencode::Integer(3)),
+        ];
         assert_eq!(left, right);
+        assert_eq!(Bencode::Integer(1).to_btree(), None);
     }
 
     #[test]
-    fn test_nested_dict_into_bytes() {
-        let left = {
+    fn test_integer_diff() {
+        let dict = |port: i64| {
             let mut h = HashMap::new();
-            let nested_dict = {
-                let mut h = HashMap::new();
-                h.insert("abc".into(), Bencode::Integer(123));
-                h.insert("def".into(), Bencode::Integer(456));
-                h
-            };
-            h.insert("nested".into(), Bencode::Dictionary(nested_dict));
+            h.insert("port".into(), Bencode::Integer(port));
+            h.insert("peers".into(), Bencode::Integer(50));
+            h.insert("name".into(), Bencode::ByteString(port.to_string().into()));
+            Bencode::Dictionary(h)
+        };
+        assert_eq!(
+            dict(6881).integer_diff(&dict(6889)),
+            vec![("port".to_string(), 6881, 6889)]
+        );
+        assert!(dict(6881).integer_diff(&dict(6881)).is_empty());
+        assert!(dict(6881).integer_diff(&Bencode::Integer(1)).is_empty());
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(Bencode::Integer(-7).summary(), "int(-7)");
+        assert_eq!(
+            Bencode::ByteString(vec![b'x'; 20]).summary(),
+            "str(20 bytes)"
+        );
+        let list = Bencode::List(vec![
+            Bencode::Integer(1),
+            Bencode::List(vec![Bencode::List(Vec::new())]),
+        ]);
+        assert_eq!(list.summary(), "list{2 items, depth 3}");
+
+        let dict = {
+            let mut h = HashMap::new();
+            h.insert("list".into(), list);
+            h.insert("name".into(), Bencode::ByteString("spam".into()));
+            Bencode::Dictionary(h)
+        };
+        assert_eq!(dict.summary(), "dict{2 keys, depth 4}");
+        assert_eq!(
+            Bencode::Dictionary(HashMap::new()).summary(),
+            "dict{0 keys, depth 1}"
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut value = Bencode::List(vec![Bencode::Integer(1), Bencode::ByteString("old".into())]);
+        if let Bencode::List(list) = &mut value {
+            let old = list[1].replace(Bencode::ByteString("new".into()));
+            assert_eq!(old, Bencode::ByteString("old".into()));
+        }
+        assert_eq!(
+            value,
+            Bencode::List(vec![Bencode::Integer(1), Bencode::ByteString("new".into())])
+        );
+    }
+
+    #[test]
+    fn test_parse_depth_limit() {
+        let nested = |depth: usize| {
+            let mut tokens = vec![Token::List; depth];
+            tokens.extend(vec![Token::End; depth]);
+            tokens
+        };
+        assert!(parse_first_with_depth(nested(3), 3).is_ok());
+        assert_eq!(
+            parse_first_with_depth(nested(4), 3),
+            Err(Error::DepthLimitExceeded)
+        );
+        assert_eq!(
+            parse_first_with_depth(vec![Token::Integer(1)], 0),
+            Ok((
+                Bencode::Integer(1),
+                1,
+                ParseStats {
+                    max_depth: 0,
+                    node_count: 1
+                }
+            ))
+        );
+        assert_eq!(
+            parse(nested(DEFAULT_MAX_DEPTH + 1)),
+            Err(Error::DepthLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn test_port() {
+        let value = {
+            let mut h = HashMap::new();
+            h.insert("port".into(), Bencode::Integer(6881));
+            h.insert("big".into(), Bencode::Integer(65536));
+            h.insert("negative".into(), Bencode::Integer(-1));
+            h.insert("name".into(), Bencode::ByteString("6881".into()));
+            Bencode::Dictionary(h)
+        };
+        assert_eq!(value.port("port"), Some(6881));
+        assert_eq!(value.port("big"), None);
+        assert_eq!(value.port("negative"), None);
+        assert_eq!(value.port("name"), None);
+        assert_eq!(value.port("absent"), None);
+        assert_eq!(Bencode::Integer(6881).port("port"), None);
+    }
+
+    #[test]
+    fn test_into_bytes_excluding() {
+        let value = {
+            let mut info = HashMap::new();
+            info.insert("creation date".into(), Bencode::Integer(7));
+            let mut h = HashMap::new();
+            h.insert("creation date".into(), Bencode::Integer(1600000000));
+            h.insert("comment".into(), Bencode::ByteString("spam".into()));
+            h.insert("info".into(), Bencode::Dictionary(info));
+            Bencode::Dictionary(h)
+        };
+        let left = value.into_bytes_excluding(&["creation date", "absent"]);
+        assert_eq!(
+            left,
+            b"d7:comment4:spam4:infod13:creation datei7eee".to_vec()
+        );
+        assert_eq!(
+            Bencode::Integer(1).into_bytes_excluding(&["creation date"]),
+            b"i1e".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut integer = Bencode::Integer(1);
+        let mut bytes = Bencode::ByteString("spam".into());
+        let mut list = Bencode::List(vec![Bencode::Integer(1)]);
+        let mut dict = Bencode::Dictionary(HashMap::new());
+
+        assert_eq!(integer.as_integer(), Some(1));
+        assert_eq!(bytes.as_bytes(), Some(b"spam".as_ref()));
+        assert_eq!(bytes.as_byte_string(), Some("spam"));
+        assert_eq!(list.as_list(), Some([Bencode::Integer(1)].as_ref()));
+        assert_eq!(dict.as_dictionary(), Some(&HashMap::new()));
+
+        assert_eq!(bytes.as_integer(), None);
+        assert_eq!(integer.as_bytes(), None);
+        assert_eq!(list.as_byte_string(), None);
+        assert_eq!(dict.as_list(), None);
+        assert_eq!(integer.as_dictionary(), None);
+
+        *integer.as_integer_mut().unwrap() += 1;
+        bytes.as_bytes_mut().unwrap().push(b's');
+        list.as_list_mut().unwrap().push(Bencode::Integer(2));
+        dict.as_dictionary_mut()
+            .unwrap()
+            .insert("key".into(), Bencode::Integer(3));
+
+        assert_eq!(integer, Bencode::Integer(2));
+        assert_eq!(bytes, Bencode::ByteString("spams".into()));
+        assert_eq!(list.as_list().map(|list| list.len()), Some(2));
+        assert_eq!(dict.as_dictionary().unwrap()["key"], Bencode::Integer(3));
+
+        assert_eq!(integer.as_list_mut(), None);
+        assert_eq!(list.as_dictionary_mut(), None);
+        assert_eq!(dict.as_bytes_mut(), None);
+        assert_eq!(bytes.as_integer_mut(), None);
+    }
+
+    #[test]
+    fn test_map_integers() {
+        let mut value = {
+            let mut h = HashMap::new();
+            h.insert("length".into(), Bencode::Integer(-21));
+            h.insert("name".into(), Bencode::ByteString("1".into()));
+            Bencode::List(vec![
+                Bencode::Integer(1),
+                Bencode::List(vec![Bencode::Integer(0)]),
+                Bencode::Dictionary(h),
+            ])
+        };
+        value.map_integers(|n| n * 2);
+        assert_eq!(
+            value.into_bytes(),
+            b"li2eli0eed6:lengthi-42e4:name1:1ee".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let value = {
+            let mut info = HashMap::new();
+            info.insert("pieces".into(), Bencode::ByteString(vec![0xff, 0x00]));
+            info.insert("name".into(), Bencode::ByteString("spam".into()));
+            let mut h = HashMap::new();
+            h.insert("info".into(), Bencode::Dictionary(info));
             h.insert(
                 "list".into(),
-                Bencode::List(vec![
-                    Bencode::Integer(1),
-                    Bencode::Integer(2),
-                    Bencode::Integer(1000),
-                ]),
+                Bencode::List(vec![Bencode::Integer(-1), Bencode::List(Vec::new())]),
             );
+            h.insert("empty".into(), Bencode::Dictionary(HashMap::new()));
             Bencode::Dictionary(h)
+        };
+        let right = concat!(
+            "{\n",
+            "  \"empty\": {},\n",
+            "  \"info\": {\n",
+            "    \"name\": \"spam\",\n",
+            "    \"pieces\": <ff00>\n",
+            "  },\n",
+            "  \"list\": [\n",
+            "    -1,\n",
+            "    []\n",
+            "  ]\n",
+            "}"
+        );
+        assert_eq!(value.to_string(), right);
+        assert_eq!(Bencode::Integer(42).to_string(), "42");
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let dict = Bencode::dict_from_iter(
+            vec!["name", "length"]
+                .into_iter()
+                .zip(vec![
+                    Bencode::ByteString("spam".into()),
+                    Bencode::Integer(1),
+                ])
+                .map(|(key, value)| (key.to_string(), value)),
+        );
+        assert_eq!(
+            dict.clone().into_bytes(),
+            b"d6:lengthi1e4:name4:spame".to_vec()
+        );
+
+        let list = Bencode::list_from_iter(vec![dict, Bencode::Integer(-1)]);
+        assert_eq!(
+            list.into_bytes(),
+            b"ld6:lengthi1e4:name4:spamei-1ee".to_vec()
+        );
+        assert_eq!(Bencode::list_from_iter(None).into_bytes(), b"le".to_vec());
+        assert_eq!(Bencode::dict_from_iter(None).into_bytes(), b"de".to_vec());
+    }
+
+    #[test]
+    fn test_parse_allocations() {
+        let mut tokens = vec![Token::List];
+        tokens.extend((0..100).map(Token::Integer));
+        tokens.push(Token::End);
+
+        // Stack of unfinished containers and the list itself.
+        let (value, counts) = instrument::count(|| parse(tokens));
+        assert_eq!(value.map(|value| value.encoded_len()), Ok(2 + 390));
+        assert_eq!(counts.allocations, 2);
+    }
+
+    #[test]
+    fn test_encode_reallocations() {
+        let value = Bencode::List((0..1000).map(Bencode::Integer).collect());
+        let cap = value.encoded_len();
+
+        let (_, growing) = instrument::count(|| value.clone().into_bytes());
+        let (_, reserved) = instrument::count(|| value.clone().into_bytes_with_capacity(cap));
+        assert!(growing.reallocations > 0);
+        assert_eq!(reserved.reallocations, 0);
+    }
+
+    #[test]
+    fn test_write_to_nested() {
+        let value = Bencode::dict_from_iter(vec![
+            ("zeta".to_string(), Bencode::Integer(-1)),
+            (
+                "alpha".to_string(),
+                Bencode::list_from_iter(vec![
+                    Bencode::ByteString(vec![0xff, 0x00]),
+                    Bencode::dict_from_iter(vec![
+                        ("y".to_string(), Bencode::Integer(2)),
+                        ("x".to_string(), Bencode::List(Vec::new())),
+                    ]),
+                ]),
+            ),
+        ]);
+
+        let mut left = Vec::new();
+        value.write_to(&mut left).unwrap();
+
+        assert_eq!(left, value.into_bytes());
+    }
+
+    #[test]
+    fn test_get_list_dict() {
+        let file = |length: i64, path: &str| {
+            Bencode::dict_from_iter(vec![
+                ("length".to_string(), Bencode::Integer(length)),
+                (
+                    "path".to_string(),
+                    Bencode::List(vec![Bencode::ByteString(path.into())]),
+                ),
+            ])
+        };
+        let info = Bencode::dict_from_iter(vec![
+            (
+                "files".to_string(),
+                Bencode::List(vec![file(1, "a.txt"), file(2, "b.txt")]),
+            ),
+            (
+                "mixed".to_string(),
+                Bencode::List(vec![file(3, "c.txt"), Bencode::Integer(4)]),
+            ),
+        ]);
+
+        let files = info.get_list_dict("files").unwrap();
+        let lengths = files
+            .iter()
+            .map(|file| file["length"].clone())
+            .collect::<Vec<Bencode>>();
+        assert_eq!(lengths, vec![Bencode::Integer(1), Bencode::Integer(2)]);
+
+        assert_eq!(info.get_list_dict("mixed"), None);
+        assert_eq!(info.get_list_dict("missing"), None);
+        assert_eq!(Bencode::Integer(1).get_list_dict("files"), None);
+    }
+
+    #[test]
+    fn test_iter() {
+        let list = Bencode::List(vec![
+            Bencode::Integer(1),
+            Bencode::ByteString("spam".into()),
+        ]);
+
+        let mut sum = 0;
+        for elem in list.iter() {
+            sum += elem.encoded_len();
         }
-        .into_bytes();
+        assert_eq!(sum, 3 + 6);
+        assert_eq!(
+            list.clone().into_iter().collect::<Vec<Bencode>>(),
+            list.as_list().unwrap().to_vec()
+        );
 
-        let right = b"d4:listli1ei2ei1000ee6:nestedd3:abci123e3:defi456eee".to_vec();
+        let dict = Bencode::dict_from_iter(vec![("a".to_string(), Bencode::Integer(1))]);
+        assert_eq!(dict.iter().next(), None);
+        assert_eq!(dict.into_iter().next(), None);
+        assert_eq!(Bencode::Integer(1).into_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_large_byte_strings() {
+        let blob = vec![0xff; 1024];
+        let value = Bencode::dict_from_iter(vec![
+            ("a".to_string(), Bencode::ByteString("small".into())),
+            (
+                "b".to_string(),
+                Bencode::List(vec![
+                    Bencode::ByteString(blob.clone()),
+                    Bencode::Integer(123_456_789),
+                    Bencode::ByteString(vec![0; 16]),
+                ]),
+            ),
+            (
+                "large key which is not a value".to_string(),
+                Bencode::ByteString(vec![1; 17]),
+            ),
+        ]);
+
+        assert_eq!(
+            value.large_byte_strings(16),
+            vec![blob.as_slice(), [1; 17].as_ref()]
+        );
+        assert_eq!(value.large_byte_strings(1024), Vec::<&[u8]>::new());
+        assert_eq!(value.large_byte_strings(0).len(), 4);
+    }
+
+    #[test]
+    fn test_integer_sign_counts() {
+        let value = Bencode::dict_from_iter(vec![
+            ("max".to_string(), Bencode::Integer(i64::MAX)),
+            ("min".to_string(), Bencode::Integer(i64::MIN)),
+            (
+                "list".to_string(),
+                Bencode::List(vec![
+                    Bencode::Integer(0),
+                    Bencode::Integer(-3),
+                    Bencode::Integer(7),
+                    Bencode::ByteString("-1".into()),
+                    Bencode::List(vec![Bencode::Integer(0)]),
+                ]),
+            ),
+        ]);
+
+        assert_eq!(value.integer_sign_counts(), (2, 2, 2));
+        assert_eq!(
+            Bencode::ByteString("0".into()).integer_sign_counts(),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        let dict = Bencode::dict_from_iter(vec![
+            ("foo".to_string(), Bencode::Integer(42)),
+            ("bar".to_string(), Bencode::List(Vec::new())),
+        ]);
+        assert_eq!(dict.get("foo"), Some(&Bencode::Integer(42)));
+        assert_eq!(dict.get("bar"), Some(&Bencode::List(Vec::new())));
+        assert_eq!(dict.get("baz"), None);
+
+        let bytes_keyed = Bencode::DictionaryBytes(
+            vec![
+                (b"foo".to_vec(), Bencode::Integer(1)),
+                (vec![0xff], Bencode::Integer(2)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(bytes_keyed.get("foo"), Some(&Bencode::Integer(1)));
+        assert_eq!(bytes_keyed.get("bar"), None);
+
+        let list = Bencode::List(vec![dict]);
+        assert_eq!(list.get("foo"), None);
+        assert_eq!(Bencode::ByteString("foo".into()).get("foo"), None);
+    }
+
+    #[test]
+    fn test_to_json_string() {
+        let value = Bencode::dict_from_iter(vec![
+            (
+                "info".to_string(),
+                Bencode::dict_from_iter(vec![
+                    (
+                        "name".to_string(),
+                        Bencode::ByteString("say \"hi\"\n".into()),
+                    ),
+                    (
+                        "pieces".to_string(),
+                        Bencode::ByteString(vec![0xde, 0xad, 0xbe, 0xef]),
+                    ),
+                    ("length".to_string(), Bencode::Integer(-1)),
+                ]),
+            ),
+            (
+                "announce-list".to_string(),
+                Bencode::List(vec![
+                    Bencode::List(Vec::new()),
+                    Bencode::dict_from_iter(vec![]),
+                ]),
+            ),
+        ]);
+
+        let right = [
+            "{",
+            "  \"announce-list\": [",
+            "    [],",
+            "    {}",
+            "  ],",
+            "  \"info\": {",
+            "    \"length\": -1,",
+            "    \"name\": \"say \\\"hi\\\"\\n\",",
+            "    \"pieces\": \"hex:deadbeef\"",
+            "  }",
+            "}",
+        ]
+        .join("\n");
+        assert_eq!(value.to_json_string(), right);
+        assert_eq!(
+            Bencode::ByteString(vec![0x01]).to_json_string(),
+            "\"\\u0001\""
+        );
+    }
+
+    #[test]
+    fn test_len() {
+        let cases = vec![
+            (Bencode::Integer(0), None),
+            (Bencode::ByteString(Vec::new()), Some(0)),
+            (Bencode::ByteString(vec![0xff, 0x00]), Some(2)),
+            (Bencode::List(Vec::new()), Some(0)),
+            (
+                Bencode::List(vec![Bencode::Integer(1), Bencode::List(Vec::new())]),
+                Some(2),
+            ),
+            (Bencode::Dictionary(HashMap::new()), Some(0)),
+            (
+                Bencode::dict_from_iter(vec![("a".to_string(), Bencode::Integer(1))]),
+                Some(1),
+            ),
+            (
+                Bencode::DictionaryBytes(
+                    vec![(vec![0xff], Bencode::Integer(1))]
+                        .into_iter()
+                        .collect(),
+                ),
+                Some(1),
+            ),
+        ];
+        for (value, len) in cases {
+            assert_eq!(value.len(), len);
+            assert_eq!(value.is_empty(), len.map(|len| len == 0));
+        }
+    }
+
+    #[test]
+    fn test_dict_builder() {
+        let left = Bencode::dict_builder()
+            .insert("list", vec![Bencode::Integer(1)])
+            .insert(String::from("bytes"), vec![0xffu8])
+            .insert("replaced", 1)
+            .insert("replaced", 2)
+            .build();
+        let right = Bencode::dict_from_iter(vec![
+            ("list".to_string(), Bencode::List(vec![Bencode::Integer(1)])),
+            ("bytes".to_string(), Bencode::ByteString(vec![0xff])),
+            ("replaced".to_string(), Bencode::Integer(2)),
+        ]);
         assert_eq!(left, right);
+
+        assert_eq!(
+            DictBuilder::new().build(),
+            Bencode::Dictionary(HashMap::new())
+        );
+    }
+
+    #[test]
+    fn test_ord() {
+        let int = |n| Bencode::Integer(n);
+        let bytes = |s: &[u8]| Bencode::ByteString(s.to_vec());
+        let dict = |entries: Vec<(&str, Bencode)>| {
+            Bencode::dict_from_iter(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value)),
+            )
+        };
+
+        // Across variants.
+        assert!(int(i64::MAX) < bytes(b""));
+        assert!(bytes(b"\xff") < Bencode::List(Vec::new()));
+        assert!(Bencode::List(vec![int(1)]) < dict(vec![]));
+
+        // Within variants.
+        assert!(int(-2) < int(1));
+        assert!(bytes(b"ab") < bytes(b"b"));
+        assert!(bytes(b"a") < bytes(b"ab"));
+        assert!(Bencode::List(vec![int(1), int(2)]) < Bencode::List(vec![int(1), int(3)]));
+        assert!(Bencode::List(vec![int(1)]) < Bencode::List(vec![int(1), int(0)]));
+        assert!(dict(vec![("a", int(2))]) < dict(vec![("b", int(1))]));
+        assert!(dict(vec![("a", int(1))]) < dict(vec![("a", int(2))]));
+        assert!(dict(vec![("a", int(1))]) < dict(vec![("a", int(1)), ("b", int(0))]));
+        assert_eq!(
+            dict(vec![("b", int(1)), ("a", int(2))]).cmp(&dict(vec![("a", int(2)), ("b", int(1))])),
+            Ordering::Equal
+        );
+
+        let bytes_keyed =
+            Bencode::DictionaryBytes(vec![(vec![0xff], int(1))].into_iter().collect());
+        assert!(dict(vec![("z", int(1))]) < bytes_keyed);
+        let utf8_keyed =
+            Bencode::DictionaryBytes(vec![(b"a".to_vec(), int(1))].into_iter().collect());
+        assert!(dict(vec![("a", int(1))]) < utf8_keyed);
+    }
+
+    #[test]
+    fn test_contains_key_and_keys() {
+        let dict = Bencode::dict_builder()
+            .insert("announce", "udp:a".to_string())
+            .insert(
+                "info",
+                Bencode::dict_builder()
+                    .insert("name", "spam".to_string())
+                    .build(),
+            )
+            .build();
+        assert!(dict.contains_key("announce"));
+        assert!(dict.contains_key("info"));
+        assert!(!dict.contains_key("name"));
+
+        let mut keys = dict.keys().unwrap().collect::<Vec<&String>>();
+        keys.sort();
+        assert_eq!(keys, vec!["announce", "info"]);
+        assert_eq!(
+            Bencode::Dictionary(HashMap::new())
+                .keys()
+                .map(Iterator::count),
+            Some(0)
+        );
+
+        let bytes_keyed = Bencode::DictionaryBytes(
+            vec![
+                (b"info".to_vec(), Bencode::Integer(1)),
+                (vec![0xff], Bencode::Integer(2)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert!(bytes_keyed.contains_key("info"));
+        assert!(bytes_keyed.keys().is_none());
+
+        let list = Bencode::List(vec![Bencode::ByteString("info".into())]);
+        assert!(!list.contains_key("info"));
+        assert!(list.keys().is_none());
+        assert!(Bencode::ByteString("info".into()).keys().is_none());
+    }
+
+    #[test]
+    fn test_byte_string_from_bytes() {
+        let bytes = vec![b'a', 0xc3, 0x28];
+        assert_eq!(
+            Bencode::byte_string_from_bytes(bytes.clone()),
+            Bencode::ByteString(bytes)
+        );
+
+        assert_eq!(
+            Bencode::try_byte_string_from_utf8("żółw".as_bytes().to_vec()),
+            Ok(Bencode::ByteString("żółw".as_bytes().to_vec()))
+        );
+        assert_eq!(
+            Bencode::try_byte_string_from_utf8(vec![b'a', 0xc3, 0x28]),
+            Err(Error::InvalidUtf8 { valid_up_to: 1 })
+        );
+
+        assert_eq!(
+            Bencode::byte_string_from_bytes(Vec::new()),
+            Bencode::ByteString(Vec::new())
+        );
+        assert_eq!(
+            Bencode::try_byte_string_from_utf8(Vec::new()),
+            Ok(Bencode::ByteString(Vec::new()))
+        );
+    }
+
+    #[test]
+    fn test_dictionary_bytes_accessors() {
+        let data = b"d5:filesld6:lengthi1eee4:porti6881e1:\xffi2ee";
+        let value = crate::from_bytes(data).unwrap();
+        assert!(value.as_dictionary().is_none());
+        assert_eq!(value.as_dictionary_bytes().map(HashMap::len), Some(3));
+
+        assert_eq!(value.port("port"), Some(6881));
+        assert_eq!(
+            value.get_list_dict("files").map(|files| files.len()),
+            Some(1)
+        );
+        assert_eq!(
+            value.top_level_key_sizes(),
+            Some(vec![
+                ("files".to_string(), 15),
+                ("port".to_string(), 6),
+                ("\u{fffd}".to_string(), 3)
+            ])
+        );
+        assert_eq!(
+            value.to_btree().unwrap().keys().collect::<Vec<&String>>(),
+            vec!["files", "port", "\u{fffd}"]
+        );
+
+        let other = crate::from_bytes(b"d4:porti6882e1:\xffi3ee").unwrap();
+        assert_eq!(
+            value.integer_diff(&other),
+            vec![("port".into(), 6881, 6882), ("\u{fffd}".into(), 2, 3)]
+        );
+        assert_eq!(
+            Bencode::List(vec![value.clone()]).to_table(&["port"]),
+            Some(vec![vec![Some(Bencode::Integer(6881))]])
+        );
+
+        assert_eq!(
+            value.clone().into_bytes_excluding(&["port"]),
+            b"d5:filesld6:lengthi1eee1:\xffi2ee".to_vec()
+        );
+        assert_eq!(
+            value.into_bytes_with_order(&["files", "port"]),
+            Err(Error::MissingKey("\u{fffd}".into()))
+        );
     }
 }
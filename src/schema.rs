@@ -0,0 +1,247 @@
+//! Schema module contains data structures and procedures related
+//! to validating dictionaries against their expected shape.
+use crate::parser::Bencode;
+
+use std::{error, fmt};
+
+/// Type of the bencoded value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Integer,
+    ByteString,
+    List,
+    Dictionary,
+}
+
+impl Kind {
+    /// Returns type of given value.
+    pub fn of(value: &Bencode) -> Self {
+        match value {
+            Bencode::Integer(_) => Kind::Integer,
+            Bencode::ByteString(_) => Kind::ByteString,
+            Bencode::List(_) => Kind::List,
+            Bencode::Dictionary(_) | Bencode::DictionaryBytes(_) => Kind::Dictionary,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Integer => write!(f, "integer"),
+            Kind::ByteString => write!(f, "byte string"),
+            Kind::List => write!(f, "list"),
+            Kind::Dictionary => write!(f, "dictionary"),
+        }
+    }
+}
+
+/// Single difference between validated value and the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// Validated value is not a dictionary.
+    NotDictionary(Kind),
+    /// Required key is absent.
+    MissingKey(String),
+    /// Value under the key has unexpected type.
+    InvalidKind {
+        key: String,
+        expected: Kind,
+        found: Kind,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::NotDictionary(found) => write!(f, "Expected dictionary, found {}.", found),
+            Mismatch::MissingKey(key) => write!(f, "Required key \"{}\" is absent.", key),
+            Mismatch::InvalidKind {
+                key,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Expected {} under key \"{}\", found {}.",
+                expected, key, found
+            ),
+        }
+    }
+}
+
+/// Represents all differences found during validation against the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Value does not match the schema:")?;
+        for mismatch in self.mismatches.iter() {
+            write!(f, " {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Field {
+    key: String,
+    kind: Kind,
+    required: bool,
+}
+
+/// Describes expected types of values stored under the keys of dictionary.
+///
+/// # Examples
+///
+/// ```
+/// use std::convert::TryInto;
+/// use bensor::Bencode;
+/// use bensor::schema::{Kind, Schema};
+///
+/// let schema = Schema::new()
+///     .required("announce", Kind::ByteString)
+///     .required("info", Kind::Dictionary)
+///     .optional("comment", Kind::ByteString);
+///
+/// let torrent: Bencode = "d8:announce5:udp:a4:infodee".try_into().unwrap();
+/// assert!(torrent.validate_schema(&schema).is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+impl Schema {
+    /// Creates schema without any fields.
+    pub fn new() -> Self {
+        Schema { fields: Vec::new() }
+    }
+
+    /// Adds key, which has to be present and hold value of given type.
+    pub fn required(mut self, key: &str, kind: Kind) -> Self {
+        self.fields.push(Field {
+            key: key.to_string(),
+            kind,
+            required: true,
+        });
+        self
+    }
+
+    /// Adds key, which may be absent, but if present has to hold value of given type.
+    pub fn optional(mut self, key: &str, kind: Kind) -> Self {
+        self.fields.push(Field {
+            key: key.to_string(),
+            kind,
+            required: false,
+        });
+        self
+    }
+}
+
+impl Bencode {
+    /// Validates dictionary against given schema, returning
+    /// every mismatch found. Keys absent from the schema are allowed.
+    pub fn validate_schema(&self, schema: &Schema) -> Result<(), Error> {
+        if !self.is_dictionary() {
+            return Err(Error {
+                mismatches: vec![Mismatch::NotDictionary(Kind::of(self))],
+            });
+        }
+
+        let mismatches = schema
+            .fields
+            .iter()
+            .filter_map(|field| match self.get(&field.key) {
+                Some(value) if Kind::of(value) != field.kind => Some(Mismatch::InvalidKind {
+                    key: field.key.clone(),
+                    expected: field.kind,
+                    found: Kind::of(value),
+                }),
+                None if field.required => Some(Mismatch::MissingKey(field.key.clone())),
+                _ => None,
+            })
+            .collect::<Vec<Mismatch>>();
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(Error { mismatches })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn torrent_schema() -> Schema {
+        Schema::new()
+            .required("announce", Kind::ByteString)
+            .required("info", Kind::Dictionary)
+            .optional("creation date", Kind::Integer)
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        let torrent: Bencode = "d8:announce5:udp:a13:creation datei1e4:infod4:name1:aee"
+            .try_into()
+            .unwrap();
+        assert_eq!(torrent.validate_schema(&torrent_schema()), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_schema_mismatches() {
+        let torrent: Bencode = "d13:creation date5:todaye".try_into().unwrap();
+
+        let right = Error {
+            mismatches: vec![
+                Mismatch::MissingKey("announce".into()),
+                Mismatch::MissingKey("info".into()),
+                Mismatch::InvalidKind {
+                    key: "creation date".into(),
+                    expected: Kind::Integer,
+                    found: Kind::ByteString,
+                },
+            ],
+        };
+        assert_eq!(torrent.validate_schema(&torrent_schema()), Err(right));
+    }
+
+    #[test]
+    fn test_validate_schema_not_dictionary() {
+        let right = Error {
+            mismatches: vec![Mismatch::NotDictionary(Kind::List)],
+        };
+        assert_eq!(
+            Bencode::List(Vec::new()).validate_schema(&torrent_schema()),
+            Err(right)
+        );
+    }
+
+    #[test]
+    fn test_validate_schema_non_utf8_keys() {
+        let torrent: Bencode = b"d13:creation date5:today1:\xffi0ee"
+            .as_ref()
+            .try_into()
+            .unwrap();
+
+        let right = Error {
+            mismatches: vec![
+                Mismatch::MissingKey("announce".into()),
+                Mismatch::MissingKey("info".into()),
+                Mismatch::InvalidKind {
+                    key: "creation date".into(),
+                    expected: Kind::Integer,
+                    found: Kind::ByteString,
+                },
+            ],
+        };
+        assert_eq!(torrent.validate_schema(&torrent_schema()), Err(right));
+    }
+}
@@ -0,0 +1,217 @@
+//! Serialize module contains data structures and procedures related to
+//! converting `Bencode` from and into [serde](https://serde.rs) data formats.
+//!
+//! Integers, lists and dictionaries map directly onto their serde
+//! counterparts. Byte strings containing valid UTF-8 become strings. Other
+//! byte strings become a map with the single `$bytes` key, holding the bytes
+//! themselves, so in JSON `2:\xff\x01` is `{"$bytes":[255,1]}`. Dictionary
+//! with the `$bytes` key only, holding byte string or list of bytes, is
+//! therefore read back as byte string. Keys of the dictionaries that are not valid
+//! UTF-8 are kept as bytes, which formats with string keys only, such as
+//! JSON, report as an error.
+use crate::parser::{self, Bencode};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+/// Key of the map standing for byte string that is not valid UTF-8.
+const BYTES_TAG: &str = "$bytes";
+
+/// Serializes byte string as string, if it contains valid UTF-8.
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for Bytes<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match str::from_utf8(self.0) {
+            Ok(string) => serializer.serialize_str(string),
+            Err(_) => serializer.serialize_bytes(self.0),
+        }
+    }
+}
+
+impl Serialize for Bencode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Bencode::Integer(int) => serializer.serialize_i64(*int),
+            Bencode::ByteString(bytes) => match str::from_utf8(bytes) {
+                Ok(string) => serializer.serialize_str(string),
+                Err(_) => {
+                    let mut map = serializer.serialize_map(Some(1))?;
+                    map.serialize_entry(BYTES_TAG, &Bytes(bytes))?;
+                    map.end()
+                }
+            },
+            Bencode::List(list) => serializer.collect_seq(list),
+            Bencode::Dictionary(dict) => serializer.collect_map(dict),
+            Bencode::DictionaryBytes(dict) => {
+                serializer.collect_map(dict.iter().map(|(key, value)| (Bytes(key), value)))
+            }
+        }
+    }
+}
+
+/// Deserializes key of the dictionary from string or bytes.
+struct Key(Vec<u8>);
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = Key;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "string or bytes")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Key, E> {
+                Ok(Key(v.as_bytes().to_vec()))
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> Result<Key, E> {
+                Ok(Key(v.into_bytes()))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Key, E> {
+                Ok(Key(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Key, E> {
+                Ok(Key(v))
+            }
+        }
+
+        deserializer.deserialize_any(KeyVisitor)
+    }
+}
+
+/// Returns bytes held by the value of the `$bytes` key.
+fn tagged_bytes(value: &Bencode) -> Option<Vec<u8>> {
+    match value {
+        Bencode::ByteString(bytes) => Some(bytes.clone()),
+        Bencode::List(list) => list
+            .iter()
+            .map(|item| match item {
+                Bencode::Integer(int) => u8::try_from(*int).ok(),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+struct BencodeVisitor;
+
+impl<'de> Visitor<'de> for BencodeVisitor {
+    type Value = Bencode;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "integer, string, bytes, sequence or map")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Bencode, E> {
+        Ok(Bencode::Integer(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Bencode, E> {
+        i64::try_from(v)
+            .map(Bencode::Integer)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Bencode, E> {
+        Ok(Bencode::ByteString(v.as_bytes().to_vec()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Bencode, E> {
+        Ok(Bencode::ByteString(v.into_bytes()))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Bencode, E> {
+        Ok(Bencode::ByteString(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Bencode, E> {
+        Ok(Bencode::ByteString(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Bencode, A::Error> {
+        let mut list = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            list.push(item);
+        }
+        Ok(Bencode::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Bencode, A::Error> {
+        let mut entries = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((Key(key), value)) = map.next_entry()? {
+            entries.insert(key, value);
+        }
+        if entries.len() == 1 {
+            if let Some(bytes) = entries.get(BYTES_TAG.as_bytes()).and_then(tagged_bytes) {
+                return Ok(Bencode::ByteString(bytes));
+            }
+        }
+        Ok(parser::dictionary(entries))
+    }
+}
+
+impl<'de> Deserialize<'de> for Bencode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(BencodeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_bytes;
+
+    fn round_trip(value: &Bencode) -> Bencode {
+        let json = serde_json::to_string(value).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_serde_json_round_trip() {
+        let value = from_bytes(b"d3:bar2:\xff\x013:fooli-1e4:spamde0:leee").unwrap();
+        assert_eq!(
+            serde_json::to_string(&value.get("bar")).unwrap(),
+            "{\"$bytes\":[255,1]}"
+        );
+        assert_eq!(round_trip(&value), value);
+
+        let value = Bencode::ByteString(Vec::new());
+        assert_eq!(serde_json::to_string(&value).unwrap(), "\"\"");
+        assert_eq!(round_trip(&value), value);
+    }
+
+    #[test]
+    fn test_serde_json_tag() {
+        let value: Bencode = serde_json::from_str("{\"$bytes\":\"spam\"}").unwrap();
+        assert_eq!(value, Bencode::ByteString(b"spam".to_vec()));
+
+        // Tag with other keys or values holding no bytes is a dictionary.
+        let value: Bencode = serde_json::from_str("{\"$bytes\":[256]}").unwrap();
+        assert_eq!(value, from_bytes(b"d6:$bytesli256eee").unwrap());
+        let value: Bencode = serde_json::from_str("{\"$bytes\":[1],\"a\":1}").unwrap();
+        assert_eq!(value, from_bytes(b"d6:$bytesli1ee1:ai1ee").unwrap());
+    }
+
+    #[test]
+    fn test_serde_json_errors() {
+        assert!(serde_json::from_str::<Bencode>("1.5").is_err());
+        assert!(serde_json::from_str::<Bencode>("null").is_err());
+        assert!(serde_json::from_str::<Bencode>("true").is_err());
+        assert!(serde_json::from_str::<Bencode>("18446744073709551615").is_err());
+
+        let value = from_bytes(b"d1:\xffi1ee").unwrap();
+        assert!(serde_json::to_string(&value).is_err());
+    }
+}
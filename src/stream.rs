@@ -0,0 +1,575 @@
+//! Stream module contains data structures and procedures
+//! related to decoding values from data arriving in parts.
+use crate::parser::Bencode;
+use crate::{from_bytes, lexer, Error};
+
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Buffers partial input and decodes top-level values
+/// as soon as they are complete.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{Bencode, StreamDecoder};
+///
+/// let mut decoder = StreamDecoder::new();
+/// decoder.feed(b"i4");
+/// assert_eq!(decoder.next_value(), None);
+///
+/// decoder.feed(b"2ei7e");
+/// assert_eq!(decoder.next_value(), Some(Ok(Bencode::Integer(42))));
+/// assert_eq!(decoder.next_value(), Some(Ok(Bencode::Integer(7))));
+/// assert_eq!(decoder.next_value(), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamDecoder {
+    buf: Vec<u8>,
+    /// Offset of the first byte in the buffer which has not been decoded.
+    start: usize,
+    max_value_len: usize,
+    scan: Scan,
+}
+
+impl StreamDecoder {
+    /// Creates decoder with empty buffer.
+    pub fn new() -> Self {
+        Self::with_max_value_len(usize::MAX)
+    }
+
+    /// Creates decoder with empty buffer, which fails with `ValueTooLong`
+    /// as soon as it is clear that the value being decoded takes more
+    /// than `max_value_len` bytes, for example because of length declared
+    /// by its byte string. This bounds the amount of data buffered at once.
+    pub fn with_max_value_len(max_value_len: usize) -> Self {
+        StreamDecoder {
+            buf: Vec::new(),
+            start: 0,
+            max_value_len,
+            scan: Scan::default(),
+        }
+    }
+
+    /// Appends given bytes to the buffered input.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Returns the next complete value, or `None` if more input is needed.
+    /// Value with malformed content is reported and skipped, leaving values
+    /// buffered after it in place. If the end of the value cannot be found
+    /// because of malformed input, all buffered data is discarded instead.
+    /// Scanning resumes where the previous call stopped, so feeding the
+    /// value in many parts does not rescan its beginning.
+    pub fn next_value(&mut self) -> Option<Result<Bencode, Error>> {
+        match self
+            .scan
+            .advance(&self.buf[self.start..], self.max_value_len)
+        {
+            Ok(Some(len)) => {
+                self.scan = Scan::default();
+                let res = from_bytes(&self.pending()[..len]);
+                self.consume(len);
+                Some(res)
+            }
+            Ok(None) => None,
+            Err((_, err)) => {
+                self.scan = Scan::default();
+                self.consume(self.pending().len());
+                Some(Err(err))
+            }
+        }
+    }
+
+    /// Returns buffered bytes which have not been decoded yet.
+    fn pending(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    /// Marks given number of pending bytes as decoded. Decoded bytes are
+    /// removed from the buffer once they make up at least half of it, so
+    /// every byte is moved a bounded number of times on average.
+    fn consume(&mut self, len: usize) {
+        self.start += len;
+        if self.start * 2 >= self.buf.len() {
+            self.buf.drain(..self.start);
+            self.start = 0;
+        }
+    }
+}
+
+impl Default for StreamDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bencode {
+    /// Parses value read from given reader, in chunks, as soon as it is
+    /// complete. Data read past the end of the value is discarded, so
+    /// position of the reader afterwards is unspecified. Use
+    /// `from_buf_reader` to read more data after the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use bensor::Bencode;
+    ///
+    /// let value = Bencode::from_reader(Cursor::new(b"li42ee")).unwrap();
+    /// assert_eq!(value, Bencode::List(vec![Bencode::Integer(42)]));
+    /// ```
+    pub fn from_reader<R: Read>(reader: R) -> Result<Bencode, Error> {
+        Self::from_buf_reader(BufReader::new(reader), usize::MAX)
+    }
+
+    /// Parses value read from given buffered reader as soon as it is
+    /// complete, consuming only the bytes of the value, so the reader
+    /// is left right after it. Fails with `ValueTooLong` if the value
+    /// takes more than `max_len` bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use bensor::Bencode;
+    ///
+    /// let mut reader = Cursor::new(b"i42e4:spam");
+    /// assert_eq!(Bencode::from_buf_reader(&mut reader, 16), Ok(Bencode::Integer(42)));
+    /// assert_eq!(reader.position(), 4);
+    /// ```
+    pub fn from_buf_reader<R: BufRead>(mut reader: R, max_len: usize) -> Result<Bencode, Error> {
+        let mut decoder = StreamDecoder::with_max_value_len(max_len);
+        loop {
+            let chunk = match reader.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            if chunk.is_empty() {
+                // Data ended before the value was complete.
+                return from_bytes(decoder.pending());
+            }
+            let len = chunk.len();
+            decoder.feed(chunk);
+            match decoder.next_value() {
+                Some(res) => {
+                    // Only bytes following the value are left pending.
+                    reader.consume(len - decoder.pending().len());
+                    return res;
+                }
+                None => reader.consume(len),
+            }
+        }
+    }
+}
+
+/// Parses all top-level values concatenated in given slice of bytes. On
+/// malformed value, its error is recorded along with the offset it starts
+/// at, and parsing resumes at the next byte after the malformed part that
+/// can start a value. Consecutive failures while looking for such byte are
+/// recorded once. Value that is not complete when the data ends takes the
+/// rest of the data, so every byte is looked at a bounded number of times.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{parse_many_lenient, Bencode};
+///
+/// let (values, errors) = parse_many_lenient(b"i1exi2e");
+/// assert_eq!(values, vec![Bencode::Integer(1), Bencode::Integer(2)]);
+/// assert_eq!(errors.len(), 1);
+/// ```
+pub fn parse_many_lenient(data: &[u8]) -> (Vec<Bencode>, Vec<(usize, Error)>) {
+    let mut values = Vec::new();
+    let mut errors = Vec::new();
+    let mut index = 0;
+    let mut resyncing = false;
+    while index < data.len() {
+        let rest = &data[index..];
+        // Length of the part to skip if the value turns out to be malformed.
+        let (res, skip) = match value_len(rest) {
+            Ok(Some(len)) => (from_bytes(&rest[..len]), len),
+            Ok(None) => (from_bytes(rest), rest.len()),
+            Err((pos, err)) => (Err(err), pos + 1),
+        };
+        match res {
+            Ok(value) => {
+                values.push(value);
+                index += skip;
+                resyncing = false;
+            }
+            Err(err) => {
+                if !resyncing {
+                    errors.push((index, err));
+                    resyncing = true;
+                }
+                index += skip;
+                while index < data.len() && !lexer::looks_like_bencode(&data[index..]) {
+                    index += 1;
+                }
+            }
+        }
+    }
+    (values, errors)
+}
+
+/// Returns length of the first complete value in given slice of bytes,
+/// or `None` if the slice ends before the value is complete. Only
+/// boundaries of tokens are checked, not their content. Error comes with
+/// the offset of the token that caused it.
+fn value_len(slice: &[u8]) -> Result<Option<usize>, (usize, Error)> {
+    Scan::default().advance(slice, usize::MAX)
+}
+
+/// Progress of looking for the end of the first value: offset of the first
+/// token not scanned yet and number of containers opened before it.
+#[derive(Debug, Clone, Default)]
+struct Scan {
+    index: usize,
+    depth: usize,
+}
+
+impl Scan {
+    /// Continues scanning given slice, which must begin with the bytes
+    /// scanned before. Returns the same as `value_len`, but fails with
+    /// `ValueTooLong` once the value is known to take more than `max_len`
+    /// bytes. Token cut off by the end of the slice is scanned again by
+    /// the next call.
+    fn advance(&mut self, slice: &[u8], max_len: usize) -> Result<Option<usize>, (usize, Error)> {
+        let too_long = (0, Error::ValueTooLong { limit: max_len });
+        match self.scan(slice, max_len) {
+            Ok(Some(len)) if len > max_len => Err(too_long),
+            Ok(None) if slice.len() > max_len => Err(too_long),
+            res => res,
+        }
+    }
+
+    /// Scans given slice like `advance` does, only checking that lengths
+    /// declared by byte strings do not exceed `max_len`.
+    fn scan(&mut self, slice: &[u8], max_len: usize) -> Result<Option<usize>, (usize, Error)> {
+        loop {
+            let index = self.index;
+            let byte = match slice.get(index) {
+                Some(byte) => *byte,
+                None => return Ok(None),
+            };
+            match byte {
+                b'd' | b'l' => {
+                    self.depth += 1;
+                    self.index += 1;
+                }
+                b'e' if self.depth > 0 => {
+                    self.depth -= 1;
+                    self.index += 1;
+                }
+                b'i' => match slice[index..].iter().position(|&c| c == b'e') {
+                    Some(pos) => self.index += pos + 1,
+                    None => return Ok(None),
+                },
+                b'0'..=b'9' => {
+                    let digits = slice[index..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_digit())
+                        .count();
+                    match slice.get(index + digits) {
+                        Some(b':') => {
+                            let size: usize = slice[index..index + digits]
+                                .iter()
+                                .map(|&c| c as char)
+                                .collect::<String>()
+                                .parse()
+                                .map_err(|_| (index, Error::Lexer(lexer::Error::ReadByteString)))?;
+                            let end = (index + digits + 1)
+                                .checked_add(size)
+                                .ok_or((index, Error::Lexer(lexer::Error::ReadByteString)))?;
+                            if end > max_len {
+                                return Err((0, Error::ValueTooLong { limit: max_len }));
+                            }
+                            if end > slice.len() {
+                                return Ok(None);
+                            }
+                            self.index = end;
+                        }
+                        Some(_) => return Err((index, Error::Lexer(lexer::Error::ReadByteString))),
+                        None => return Ok(None),
+                    }
+                }
+                c => return Err((index, Error::Lexer(lexer::Error::ReadFirstByte(c as char)))),
+            }
+            if self.depth == 0 {
+                return Ok(Some(self.index));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_feed_in_parts() {
+        let mut decoder = StreamDecoder::new();
+
+        decoder.feed(b"d3:foo");
+        assert_eq!(decoder.next_value(), None);
+        decoder.feed(b"l4:sp");
+        assert_eq!(decoder.next_value(), None);
+        decoder.feed(b"ami42eee");
+
+        let right = {
+            let mut dict = HashMap::new();
+            dict.insert(
+                "foo".into(),
+                Bencode::List(vec![
+                    Bencode::ByteString("spam".into()),
+                    Bencode::Integer(42),
+                ]),
+            );
+            Bencode::Dictionary(dict)
+        };
+
+        assert_eq!(decoder.next_value(), Some(Ok(right)));
+        assert_eq!(decoder.next_value(), None);
+    }
+
+    #[test]
+    fn test_feed_resumes_scan() {
+        let mut decoder = StreamDecoder::new();
+        decoder.feed(b"l");
+        for _ in 0..3 {
+            decoder.feed(b"i1e4:sp");
+            assert_eq!(decoder.next_value(), None);
+            decoder.feed(b"am");
+            assert_eq!(decoder.next_value(), None);
+        }
+        assert_eq!(decoder.scan.index, 28);
+        assert_eq!(decoder.scan.depth, 1);
+
+        decoder.feed(b"ei7e");
+        assert_eq!(
+            decoder.next_value(),
+            Some(from_bytes(b"li1e4:spami1e4:spami1e4:spame"))
+        );
+        assert_eq!(decoder.scan.index, 0);
+        assert_eq!(decoder.next_value(), Some(Ok(Bencode::Integer(7))));
+        assert_eq!(decoder.next_value(), None);
+    }
+
+    #[test]
+    fn test_feed_malformed() {
+        let mut decoder = StreamDecoder::new();
+        decoder.feed(b"lxe");
+
+        assert_eq!(
+            decoder.next_value(),
+            Some(Err(Error::Lexer(lexer::Error::ReadFirstByte('x'))))
+        );
+        assert_eq!(decoder.next_value(), None);
+    }
+
+    #[test]
+    fn test_feed_malformed_content() {
+        let mut decoder = StreamDecoder::new();
+        decoder.feed(b"i01ei2e3:foo");
+
+        assert_eq!(
+            decoder.next_value(),
+            Some(Err(Error::NonCanonicalInteger { offset: 0 }))
+        );
+        assert_eq!(decoder.next_value(), Some(Ok(Bencode::Integer(2))));
+        assert_eq!(
+            decoder.next_value(),
+            Some(Ok(Bencode::ByteString("foo".into())))
+        );
+        assert_eq!(decoder.next_value(), None);
+    }
+
+    #[test]
+    fn test_feed_compacts_buffer() {
+        let mut decoder = StreamDecoder::new();
+        decoder.feed(&b"i1e".repeat(1000));
+        decoder.feed(b"i2");
+
+        for _ in 0..500 {
+            assert_eq!(decoder.next_value(), Some(Ok(Bencode::Integer(1))));
+        }
+        assert_eq!(decoder.start, 1500);
+        assert_eq!(decoder.buf.len(), 3002);
+
+        // Decoded half of the buffer is removed from it at once.
+        assert_eq!(decoder.next_value(), Some(Ok(Bencode::Integer(1))));
+        assert_eq!(decoder.start, 0);
+        assert_eq!(decoder.buf.len(), 1499);
+
+        for _ in 0..499 {
+            assert_eq!(decoder.next_value(), Some(Ok(Bencode::Integer(1))));
+        }
+        assert_eq!(decoder.next_value(), None);
+        assert_eq!(decoder.pending(), b"i2");
+        decoder.feed(b"e");
+        assert_eq!(decoder.next_value(), Some(Ok(Bencode::Integer(2))));
+        assert!(decoder.buf.is_empty());
+    }
+
+    #[test]
+    fn test_feed_value_too_long() {
+        let too_long = Some(Err(Error::ValueTooLong { limit: 8 }));
+
+        // Declared length is rejected before the byte string is buffered.
+        let mut decoder = StreamDecoder::with_max_value_len(8);
+        decoder.feed(b"999999999999:");
+        assert_eq!(decoder.next_value(), too_long);
+        assert_eq!(decoder.next_value(), None);
+
+        decoder.feed(b"llllllllll");
+        assert_eq!(decoder.next_value(), too_long);
+
+        decoder.feed(b"l4:spame4:eggs");
+        assert_eq!(decoder.next_value(), Some(from_bytes(b"l4:spame")));
+        assert_eq!(
+            decoder.next_value(),
+            Some(Ok(Bencode::ByteString("eggs".into())))
+        );
+    }
+
+    #[test]
+    fn test_parse_many_lenient() {
+        let (values, errors) = parse_many_lenient(b"i1elxe4:spam");
+        assert_eq!(
+            values,
+            vec![Bencode::Integer(1), Bencode::ByteString("spam".into())]
+        );
+        assert_eq!(
+            errors,
+            vec![(3, Error::Lexer(lexer::Error::ReadFirstByte('x')))]
+        );
+
+        let (values, errors) = parse_many_lenient(b"i1ei2");
+        assert_eq!(values, vec![Bencode::Integer(1)]);
+        assert_eq!(
+            errors,
+            vec![(
+                3,
+                Error::Lexer(lexer::Error::UnexpectedEof { context: "integer" })
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_many_lenient_resync() {
+        // Values inside the malformed part are skipped along with it.
+        let (values, errors) = parse_many_lenient(b"li1ei2exi3e");
+        assert_eq!(values, vec![Bencode::Integer(3)]);
+        assert_eq!(
+            errors,
+            vec![(0, Error::Lexer(lexer::Error::ReadFirstByte('x')))]
+        );
+
+        // Well-formed boundaries with malformed content are skipped whole.
+        let (values, errors) = parse_many_lenient(b"li1xeei2e");
+        assert_eq!(values, vec![Bencode::Integer(2)]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 0);
+
+        let mut data = "l".repeat(100_000).into_bytes();
+        data.extend_from_slice(b"xi1e");
+        let (values, errors) = parse_many_lenient(&data);
+        assert_eq!(values, vec![Bencode::Integer(1)]);
+        assert_eq!(errors.len(), 1);
+    }
+
+    /// Reader yielding one byte at a time, failing at the end if requested.
+    struct ByteReader<'a> {
+        data: &'a [u8],
+        fail: bool,
+    }
+
+    impl<'a> Read for ByteReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.data.split_first() {
+                Some((byte, rest)) => {
+                    buf[0] = *byte;
+                    self.data = rest;
+                    Ok(1)
+                }
+                None if self.fail => Err(io::Error::other("broken")),
+                None => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_reader_cursor() {
+        let data = b"d3:fooli1e4:spamee".to_vec();
+        let left = Bencode::from_reader(io::Cursor::new(&data)).unwrap();
+        assert_eq!(left, from_bytes(&data).unwrap());
+    }
+
+    #[test]
+    fn test_from_reader_byte_at_a_time() {
+        let data = b"d3:fooli1e4:spamee";
+        let reader = ByteReader { data, fail: false };
+        assert_eq!(Bencode::from_reader(reader), from_bytes(data));
+
+        let reader = ByteReader {
+            data: b"li1e",
+            fail: false,
+        };
+        assert_eq!(
+            Bencode::from_reader(reader),
+            Err(Error::Parser(parser::Error::NoEndList))
+        );
+    }
+
+    #[test]
+    fn test_from_buf_reader() {
+        let mut reader = io::Cursor::new(b"i1eli2ee3:foo".to_vec());
+        assert_eq!(
+            Bencode::from_buf_reader(&mut reader, 16),
+            Ok(Bencode::Integer(1))
+        );
+        assert_eq!(reader.position(), 3);
+        assert_eq!(
+            Bencode::from_buf_reader(&mut reader, 16),
+            from_bytes(b"li2ee")
+        );
+        assert_eq!(
+            Bencode::from_buf_reader(&mut reader, 16),
+            Ok(Bencode::ByteString("foo".into()))
+        );
+
+        // Values arriving byte by byte are consumed exactly as well.
+        let data = b"d3:fooli1e4:spamee4:eggs";
+        let mut reader = io::BufReader::new(ByteReader { data, fail: false });
+        assert_eq!(
+            Bencode::from_buf_reader(&mut reader, 64),
+            from_bytes(&data[..18])
+        );
+        assert_eq!(
+            Bencode::from_buf_reader(&mut reader, 64),
+            Ok(Bencode::ByteString("eggs".into()))
+        );
+
+        let mut reader = io::Cursor::new(b"1000000:".to_vec());
+        assert_eq!(
+            Bencode::from_buf_reader(&mut reader, 64),
+            Err(Error::ValueTooLong { limit: 64 })
+        );
+    }
+
+    #[test]
+    fn test_from_reader_io_error() {
+        let reader = ByteReader {
+            data: b"li1e",
+            fail: true,
+        };
+        match Bencode::from_reader(reader) {
+            Err(Error::Io { kind, .. }) => assert_eq!(kind, io::ErrorKind::Other),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
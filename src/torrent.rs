@@ -0,0 +1,861 @@
+//! Torrent module contains helpers for navigating
+//! bencoded metainfo (torrent) files.
+use crate::parser::Bencode;
+
+use std::convert::TryFrom;
+use std::path::{Component, Path, PathBuf};
+use std::{error, fmt, str};
+
+/// Represents possible complications that can occur during
+/// extracting torrent data from bencoded values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Required field is absent.
+    MissingField(&'static str),
+    /// Field has unexpected type.
+    InvalidField(&'static str),
+    /// Info dictionary contains neither `length` nor `files`.
+    MissingMode,
+    /// Total length of files does not fit into `i64`.
+    LengthOverflow,
+    /// Path component could escape the download directory.
+    UnsafePath(String),
+    /// Length of `pieces` is not a multiple of the hash length.
+    InvalidPiecesLength(usize),
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingField(field) => write!(f, "Required field \"{}\" is absent.", field),
+            Error::InvalidField(field) => write!(f, "Field \"{}\" has unexpected type.", field),
+            Error::MissingMode => write!(
+                f,
+                "Info dictionary contains neither \"length\" nor \"files\"."
+            ),
+            Error::LengthOverflow => write!(f, "Total length of files is too large."),
+            Error::UnsafePath(component) => {
+                write!(f, "Path component \"{}\" is not safe.", component)
+            }
+            Error::InvalidPiecesLength(len) => write!(
+                f,
+                "Length of pieces ({}) is not a multiple of {}.",
+                len, PIECE_HASH_LEN
+            ),
+        }
+    }
+}
+
+/// Single file of the multi-file torrent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    /// Length of the file in bytes.
+    pub length: i64,
+    /// Path of the file, split into components.
+    pub path: Vec<String>,
+}
+
+impl FileEntry {
+    fn from_bencode(value: &Bencode) -> Result<Self, Error> {
+        if !value.is_dictionary() {
+            return Err(Error::InvalidField("files"));
+        }
+        let length = match value.get("length") {
+            Some(Bencode::Integer(length)) => *length,
+            Some(_) => return Err(Error::InvalidField("length")),
+            None => return Err(Error::MissingField("length")),
+        };
+        let path = match value.get("path") {
+            Some(Bencode::List(path)) => path
+                .iter()
+                .map(|component| match component {
+                    Bencode::ByteString(component) => String::from_utf8(component.clone())
+                        .map_err(|_| Error::InvalidField("path")),
+                    _ => Err(Error::InvalidField("path")),
+                })
+                .collect::<Result<Vec<String>, Error>>()?,
+            Some(_) => return Err(Error::InvalidField("path")),
+            None => return Err(Error::MissingField("path")),
+        };
+        Ok(FileEntry { length, path })
+    }
+}
+
+/// Distinguishes torrents containing single file from those containing many files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TorrentMode {
+    /// Torrent with single file of given length.
+    SingleFile { length: i64 },
+    /// Torrent with many files.
+    MultiFile { files: Vec<FileEntry> },
+}
+
+impl TorrentMode {
+    /// Extracts mode from the `info` dictionary of the torrent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    /// use bensor::torrent::TorrentMode;
+    ///
+    /// let info: Bencode = "d6:lengthi1024e4:name4:spame".try_into().unwrap();
+    /// assert_eq!(TorrentMode::from_info(&info), Ok(TorrentMode::SingleFile { length: 1024 }));
+    /// ```
+    pub fn from_info(info: &Bencode) -> Result<Self, Error> {
+        if !info.is_dictionary() {
+            return Err(Error::InvalidField("info"));
+        }
+        match (info.get("length"), info.get("files")) {
+            (Some(Bencode::Integer(length)), _) => Ok(TorrentMode::SingleFile { length: *length }),
+            (Some(_), _) => Err(Error::InvalidField("length")),
+            (None, Some(Bencode::List(files))) => Ok(TorrentMode::MultiFile {
+                files: files
+                    .iter()
+                    .map(FileEntry::from_bencode)
+                    .collect::<Result<Vec<FileEntry>, Error>>()?,
+            }),
+            (None, Some(_)) => Err(Error::InvalidField("files")),
+            (None, None) => Err(Error::MissingMode),
+        }
+    }
+}
+
+impl Bencode {
+    /// Returns web seeds ([BEP-19](http://bittorrent.org/beps/bep_0019.html)) stored
+    /// under `url-list` key, which can be either single byte string or list of them.
+    /// Like in [`Bencode::all_trackers`], entries which are not UTF-8 byte strings
+    /// are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let torrent: Bencode = "d8:url-list18:http://example.come".try_into().unwrap();
+    /// assert_eq!(torrent.web_seeds(), Some(vec!["http://example.com"]));
+    /// ```
+    pub fn web_seeds(&self) -> Option<Vec<&str>> {
+        let urls = match self.get("url-list")? {
+            url @ Bencode::ByteString(_) => std::slice::from_ref(url),
+            Bencode::List(urls) => urls.as_slice(),
+            _ => return None,
+        };
+        let urls = urls.iter().filter_map(|url| match url {
+            Bencode::ByteString(url) => str::from_utf8(url).ok(),
+            _ => None,
+        });
+        Some(urls.collect())
+    }
+
+    /// Returns all trackers of the torrent: the one stored under `announce` key
+    /// followed by tiers of `announce-list`
+    /// ([BEP-12](http://bittorrent.org/beps/bep_0012.html)), without duplicates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let torrent: Bencode = "d8:announce5:udp:a13:announce-listll5:udp:a5:udp:beee"
+    ///     .try_into()
+    ///     .unwrap();
+    /// assert_eq!(torrent.all_trackers(), vec!["udp:a", "udp:b"]);
+    /// ```
+    pub fn all_trackers(&self) -> Vec<&str> {
+        let announce = self.get("announce").into_iter();
+        let tiers = match self.get("announce-list") {
+            Some(Bencode::List(tiers)) => tiers.as_slice(),
+            _ => &[],
+        };
+        let announce_list = tiers.iter().flat_map(|tier| match tier {
+            Bencode::List(trackers) => trackers.as_slice(),
+            _ => &[],
+        });
+
+        let mut res = Vec::new();
+        for tracker in announce.chain(announce_list) {
+            if let Bencode::ByteString(url) = tracker {
+                if let Ok(url) = str::from_utf8(url) {
+                    if !res.contains(&url) {
+                        res.push(url);
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    /// Alias of [`Bencode::info_bytes`], kept for compatibility.
+    #[doc(hidden)]
+    pub fn recompute_info_hash_input(&self) -> Option<Vec<u8>> {
+        self.info_bytes()
+    }
+
+    /// Returns canonical encoding of the `info` dictionary, which is
+    /// the input of the info-hash, or `None` if `info` is absent or
+    /// is not a dictionary.
+    ///
+    /// Keep in mind that the dictionary is re-encoded: if the original torrent
+    /// had non-canonical `info` (e.g. unsorted keys), the hash of returned bytes
+    /// will not match the real info-hash. Use [`raw_info`] to get exact bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let torrent: Bencode = "d4:infod4:name1:a6:lengthi1eee".try_into().unwrap();
+    /// assert_eq!(torrent.info_bytes(), Some(b"d6:lengthi1e4:name1:ae".to_vec()));
+    ///
+    /// let torrent: Bencode = "d4:infoi1ee".try_into().unwrap();
+    /// assert_eq!(torrent.info_bytes(), None);
+    /// ```
+    pub fn info_bytes(&self) -> Option<Vec<u8>> {
+        let info = self.get("info")?;
+        if !info.is_dictionary() {
+            return None;
+        }
+        let mut res = Vec::with_capacity(info.encoded_len());
+        // Writing into vector of bytes cannot fail.
+        info.write_to(&mut res).unwrap();
+        Some(res)
+    }
+
+    /// Checks whether `Bencode` has all fields required by
+    /// [BEP-3](http://bittorrent.org/beps/bep_0003.html) `info` dictionary:
+    /// `name`, `piece length`, `pieces` and either `length` or `files`,
+    /// each of proper type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let torrent: Bencode = "d4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee"
+    ///     .try_into()
+    ///     .unwrap();
+    /// assert!(torrent.is_valid_torrent());
+    /// ```
+    pub fn is_valid_torrent(&self) -> bool {
+        let info = match self.get("info") {
+            Some(info) if info.is_dictionary() => info,
+            _ => return false,
+        };
+        matches!(info.get("name"), Some(Bencode::ByteString(_)))
+            && matches!(info.get("piece length"), Some(Bencode::Integer(_)))
+            && matches!(info.get("pieces"), Some(Bencode::ByteString(_)))
+            && TorrentMode::from_info(info).is_ok()
+    }
+
+    /// Returns creation time of the torrent, in seconds since UNIX epoch,
+    /// stored under `creation date` key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let torrent: Bencode = "d13:creation datei1600000000ee".try_into().unwrap();
+    /// assert_eq!(torrent.creation_date(), Some(1600000000));
+    /// ```
+    pub fn creation_date(&self) -> Option<i64> {
+        self.get("creation date")?.as_integer()
+    }
+
+    /// Returns name of the torrent stored in its `info` dictionary. Value of
+    /// `name.utf-8` key is preferred, if it is valid UTF-8, otherwise value
+    /// of `name` key is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use bensor::Bencode;
+    ///
+    /// let torrent: Bencode = "d4:infod4:name4:spam10:name.utf-84:eggsee".try_into().unwrap();
+    /// assert_eq!(torrent.torrent_name(), Some("eggs"));
+    /// ```
+    pub fn torrent_name(&self) -> Option<&str> {
+        let info = self.get("info")?;
+        info.get("name.utf-8")
+            .and_then(Bencode::as_byte_string)
+            .or_else(|| info.get("name")?.as_byte_string())
+    }
+}
+
+/// Returns exact bytes of the `info` dictionary, as they appear in given
+/// torrent data, or `None` if the top-level dictionary has no `info` key.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::torrent::raw_info;
+///
+/// let data = b"d4:infod4:name1:a6:lengthi1eee";
+/// assert_eq!(raw_info(data), Ok(Some(b"d4:name1:a6:lengthi1ee".as_ref())));
+/// ```
+pub fn raw_info(data: &[u8]) -> Result<Option<&[u8]>, crate::Error> {
+    let ranges = crate::key_value_ranges(data)?;
+    Ok(ranges.get("info").map(|range| &data[range.clone()]))
+}
+
+/// Checks whether two torrents have the same `info` dictionary, by comparing
+/// its canonical encodings. Returns `None` if either of them lacks `info`.
+///
+/// # Examples
+///
+/// ```
+/// use std::convert::TryInto;
+/// use bensor::Bencode;
+/// use bensor::torrent::same_info;
+///
+/// let a: Bencode = "d8:announce1:a4:infod4:name4:spamee".try_into().unwrap();
+/// let b: Bencode = "d8:announce1:b4:infod4:name4:spamee".try_into().unwrap();
+/// assert_eq!(same_info(&a, &b), Some(true));
+/// ```
+pub fn same_info(a: &Bencode, b: &Bencode) -> Option<bool> {
+    Some(a.info_bytes()? == b.info_bytes()?)
+}
+
+/// Metainfo of the torrent, extracted from its top-level dictionary.
+///
+/// # Examples
+///
+/// ```
+/// use std::convert::{TryFrom, TryInto};
+/// use bensor::Bencode;
+/// use bensor::torrent::{TorrentFile, TorrentMode};
+///
+/// let value: Bencode = "d4:infod6:lengthi1024e4:name4:spamee".try_into().unwrap();
+/// let torrent = TorrentFile::try_from(&value).unwrap();
+/// assert_eq!(torrent.name, "spam");
+/// assert_eq!(torrent.mode, TorrentMode::SingleFile { length: 1024 });
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TorrentFile {
+    /// Name of the single file, or of the directory containing many files.
+    pub name: String,
+    /// Layout of the files in the torrent.
+    pub mode: TorrentMode,
+    /// Concatenated SHA-1 hashes of the pieces, if present.
+    pub pieces: Option<Vec<u8>>,
+}
+
+const PIECE_HASH_LEN: usize = 20;
+
+impl TorrentFile {
+    /// Splits `pieces` into SHA-1 hashes of the consecutive pieces.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::{TryFrom, TryInto};
+    /// use bensor::Bencode;
+    /// use bensor::torrent::TorrentFile;
+    ///
+    /// let data = format!("d4:infod6:lengthi1e4:name1:a6:pieces20:{}ee", "x".repeat(20));
+    /// let value: Bencode = data.as_str().try_into().unwrap();
+    /// let torrent = TorrentFile::try_from(&value).unwrap();
+    /// assert_eq!(torrent.piece_hashes(), Ok(vec![[b'x'; 20]]));
+    /// ```
+    pub fn piece_hashes(&self) -> Result<Vec<[u8; PIECE_HASH_LEN]>, Error> {
+        let pieces = self.pieces.as_ref().ok_or(Error::MissingField("pieces"))?;
+        if pieces.len() % PIECE_HASH_LEN != 0 {
+            return Err(Error::InvalidPiecesLength(pieces.len()));
+        }
+        Ok(pieces
+            .chunks(PIECE_HASH_LEN)
+            .map(|chunk| {
+                let mut hash = [0; PIECE_HASH_LEN];
+                hash.copy_from_slice(chunk);
+                hash
+            })
+            .collect())
+    }
+
+    /// Returns total length of the torrent content in bytes.
+    pub fn total_length(&self) -> Result<i64, Error> {
+        match &self.mode {
+            TorrentMode::SingleFile { length } => Ok(*length),
+            TorrentMode::MultiFile { files } => files.iter().try_fold(0i64, |sum, file| {
+                sum.checked_add(file.length).ok_or(Error::LengthOverflow)
+            }),
+        }
+    }
+
+    /// Returns relative paths of all files in the torrent. Fails with
+    /// `Error::UnsafePath` if any path component is empty, refers to the
+    /// current or parent directory, or contains path separator, drive
+    /// or root on any platform (`/`, `\` or `:`), so that paths cannot
+    /// escape the directory the torrent is downloaded into.
+    /// Path without components, which would name the directory itself,
+    /// is reported as a single empty component.
+    pub fn safe_paths(&self) -> Result<Vec<PathBuf>, Error> {
+        check_component(&self.name)?;
+        match &self.mode {
+            TorrentMode::SingleFile { .. } => Ok(vec![PathBuf::from(&self.name)]),
+            TorrentMode::MultiFile { files } => files
+                .iter()
+                .map(|file| {
+                    if file.path.is_empty() {
+                        return Err(Error::UnsafePath(String::new()));
+                    }
+                    let mut path = PathBuf::from(&self.name);
+                    for component in file.path.iter() {
+                        check_component(component)?;
+                        path.push(component);
+                    }
+                    Ok(path)
+                })
+                .collect(),
+        }
+    }
+}
+
+fn check_component(component: &str) -> Result<(), Error> {
+    // Separators of other platforms are rejected as well, since they
+    // are ordinary characters of a component on the current one.
+    let mut components = Path::new(component).components();
+    let normal =
+        matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none();
+    if !normal || component.contains(['/', '\\', ':']) {
+        Err(Error::UnsafePath(component.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+impl<'a> TryFrom<&'a Bencode> for TorrentFile {
+    type Error = Error;
+
+    fn try_from(value: &'a Bencode) -> Result<Self, Self::Error> {
+        if !value.is_dictionary() {
+            return Err(Error::InvalidField("torrent"));
+        }
+        let info = value.get("info").ok_or(Error::MissingField("info"))?;
+        if !info.is_dictionary() {
+            return Err(Error::InvalidField("info"));
+        }
+        let name = match info.get("name") {
+            Some(Bencode::ByteString(name)) => {
+                String::from_utf8(name.clone()).map_err(|_| Error::InvalidField("name"))?
+            }
+            Some(_) => return Err(Error::InvalidField("name")),
+            None => return Err(Error::MissingField("name")),
+        };
+        let pieces = match info.get("pieces") {
+            Some(Bencode::ByteString(pieces)) => Some(pieces.clone()),
+            Some(_) => return Err(Error::InvalidField("pieces")),
+            None => None,
+        };
+        Ok(TorrentFile {
+            name,
+            mode: TorrentMode::from_info(info)?,
+            pieces,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_web_seeds_single() {
+        let torrent: Bencode = "d8:url-list13:http://a.com/e".try_into().unwrap();
+        assert_eq!(torrent.web_seeds(), Some(vec!["http://a.com/"]));
+    }
+
+    #[test]
+    fn test_web_seeds_list() {
+        let torrent: Bencode = "d8:url-listl13:http://a.com/13:http://b.com/ee"
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            torrent.web_seeds(),
+            Some(vec!["http://a.com/", "http://b.com/"])
+        );
+    }
+
+    #[test]
+    fn test_web_seeds_invalid_entries() {
+        let data = b"d8:url-listl13:http://a.com/1:\xffi1e13:http://b.com/ee";
+        let torrent = crate::from_bytes(data).unwrap();
+        assert_eq!(
+            torrent.web_seeds(),
+            Some(vec!["http://a.com/", "http://b.com/"])
+        );
+
+        let torrent = crate::from_bytes(b"d8:url-list1:\xffe").unwrap();
+        assert_eq!(torrent.web_seeds(), Some(vec![]));
+    }
+
+    #[test]
+    fn test_web_seeds_absent() {
+        let torrent: Bencode = "d8:announce13:http://a.com/e".try_into().unwrap();
+        assert_eq!(torrent.web_seeds(), None);
+    }
+
+    #[test]
+    fn test_all_trackers() {
+        let torrent: Bencode = concat!(
+            "d8:announce13:http://a.com/",
+            "13:announce-listll13:http://a.com/13:http://b.com/el13:http://c.com/13:http://b.com/ee",
+            "e"
+        )
+        .try_into()
+        .unwrap();
+
+        assert_eq!(
+            torrent.all_trackers(),
+            vec!["http://a.com/", "http://b.com/", "http://c.com/"]
+        );
+        assert!(Bencode::Integer(1).all_trackers().is_empty());
+    }
+
+    #[test]
+    fn test_mode_single_file() {
+        let info: Bencode = "d6:lengthi1024e4:name8:file.txte".try_into().unwrap();
+        assert_eq!(
+            TorrentMode::from_info(&info),
+            Ok(TorrentMode::SingleFile { length: 1024 })
+        );
+    }
+
+    #[test]
+    fn test_mode_multi_file() {
+        let info: Bencode = concat!(
+            "d5:filesl",
+            "d6:lengthi10e4:pathl1:a5:b.txtee",
+            "d6:lengthi20e4:pathl5:c.txtee",
+            "e4:name3:dire"
+        )
+        .try_into()
+        .unwrap();
+
+        let right = TorrentMode::MultiFile {
+            files: vec![
+                FileEntry {
+                    length: 10,
+                    path: vec!["a".into(), "b.txt".into()],
+                },
+                FileEntry {
+                    length: 20,
+                    path: vec!["c.txt".into()],
+                },
+            ],
+        };
+        assert_eq!(TorrentMode::from_info(&info), Ok(right));
+    }
+
+    #[test]
+    fn test_mode_missing() {
+        let info: Bencode = "d4:name3:dire".try_into().unwrap();
+        assert_eq!(TorrentMode::from_info(&info), Err(Error::MissingMode));
+    }
+
+    #[test]
+    fn test_total_length_single_file() {
+        let value: Bencode = "d4:infod6:lengthi1024e4:name1:aee".try_into().unwrap();
+        let torrent = TorrentFile::try_from(&value).unwrap();
+        assert_eq!(torrent.total_length(), Ok(1024));
+    }
+
+    #[test]
+    fn test_total_length_multi_file() {
+        let value: Bencode = concat!(
+            "d4:infod5:filesl",
+            "d6:lengthi10e4:pathl1:aee",
+            "d6:lengthi20e4:pathl1:bee",
+            "e4:name3:diree"
+        )
+        .try_into()
+        .unwrap();
+        let torrent = TorrentFile::try_from(&value).unwrap();
+        assert_eq!(torrent.total_length(), Ok(30));
+    }
+
+    #[test]
+    fn test_total_length_overflow() {
+        let file = |length| FileEntry {
+            length,
+            path: vec!["a".into()],
+        };
+        let torrent = TorrentFile {
+            name: "dir".into(),
+            mode: TorrentMode::MultiFile {
+                files: vec![file(i64::MAX), file(1)],
+            },
+            pieces: None,
+        };
+        assert_eq!(torrent.total_length(), Err(Error::LengthOverflow));
+    }
+
+    #[test]
+    fn test_safe_paths() {
+        let value: Bencode = concat!(
+            "d4:infod5:filesl",
+            "d6:lengthi10e4:pathl1:a5:b.txtee",
+            "d6:lengthi20e4:pathl5:c.txtee",
+            "e4:name3:diree"
+        )
+        .try_into()
+        .unwrap();
+        let torrent = TorrentFile::try_from(&value).unwrap();
+
+        let right = vec![
+            Path::new("dir").join("a").join("b.txt"),
+            Path::new("dir").join("c.txt"),
+        ];
+        assert_eq!(torrent.safe_paths(), Ok(right));
+    }
+
+    #[test]
+    fn test_safe_paths_parent_dir() {
+        let value: Bencode = concat!(
+            "d4:infod5:filesl",
+            "d6:lengthi10e4:pathl2:..6:passwdee",
+            "e4:name3:diree"
+        )
+        .try_into()
+        .unwrap();
+        let torrent = TorrentFile::try_from(&value).unwrap();
+
+        assert_eq!(torrent.safe_paths(), Err(Error::UnsafePath("..".into())));
+    }
+
+    #[test]
+    fn test_safe_paths_unsafe_components() {
+        let unsafe_components = [
+            "",
+            ".",
+            "..",
+            "/etc",
+            "a/b",
+            "a\\b",
+            "\\\\server",
+            "C:",
+            "C:\\x",
+        ];
+        for component in unsafe_components.iter() {
+            let err = Err(Error::UnsafePath(component.to_string()));
+            assert_eq!(check_component(component), err);
+        }
+        assert_eq!(check_component("..."), Ok(()));
+        assert_eq!(check_component("a.b"), Ok(()));
+
+        let value: Bencode = concat!(
+            "d4:infod5:filesl",
+            "d6:lengthi10e4:pathl2:C:6:passwdee",
+            "e4:name3:diree"
+        )
+        .try_into()
+        .unwrap();
+        let torrent = TorrentFile::try_from(&value).unwrap();
+
+        assert_eq!(torrent.safe_paths(), Err(Error::UnsafePath("C:".into())));
+    }
+
+    #[test]
+    fn test_safe_paths_empty_path() {
+        let value: Bencode = concat!(
+            "d4:infod5:filesl",
+            "d6:lengthi10e4:pathl1:aee",
+            "d6:lengthi10e4:pathlee",
+            "e4:name3:diree"
+        )
+        .try_into()
+        .unwrap();
+        let torrent = TorrentFile::try_from(&value).unwrap();
+
+        assert_eq!(torrent.safe_paths(), Err(Error::UnsafePath(String::new())));
+    }
+
+    #[test]
+    fn test_creation_date() {
+        let torrent: Bencode = "d13:creation datei1600000000ee".try_into().unwrap();
+        assert_eq!(torrent.creation_date(), Some(1600000000));
+
+        let torrent: Bencode = "d8:announce5:udp:ae".try_into().unwrap();
+        assert_eq!(torrent.creation_date(), None);
+
+        let torrent: Bencode = "d13:creation date5:todaye".try_into().unwrap();
+        assert_eq!(torrent.creation_date(), None);
+    }
+
+    #[test]
+    fn test_recompute_info_hash_input() {
+        let data = b"d8:announce5:udp:a4:infod6:lengthi1024e4:name4:spamee";
+        let torrent: Bencode = data.as_ref().try_into().unwrap();
+
+        let right = b"d6:lengthi1024e4:name4:spame".to_vec();
+        assert_eq!(torrent.recompute_info_hash_input(), Some(right.clone()));
+        assert_eq!(raw_info(data), Ok(Some(right.as_slice())));
+
+        let torrent: Bencode = "d8:announce5:udp:ae".try_into().unwrap();
+        assert_eq!(torrent.recompute_info_hash_input(), None);
+        let torrent: Bencode = "d4:infoi1ee".try_into().unwrap();
+        assert_eq!(torrent.recompute_info_hash_input(), None);
+    }
+
+    #[test]
+    fn test_torrent_name() {
+        let torrent: Bencode = "d4:infod4:name4:spam10:name.utf-84:eggsee"
+            .try_into()
+            .unwrap();
+        assert_eq!(torrent.torrent_name(), Some("eggs"));
+
+        let torrent: Bencode = "d4:infod4:name4:spamee".try_into().unwrap();
+        assert_eq!(torrent.torrent_name(), Some("spam"));
+
+        let torrent: Bencode = b"d4:infod4:name4:spam10:name.utf-82:\xff\xfeee"
+            .as_ref()
+            .try_into()
+            .unwrap();
+        assert_eq!(torrent.torrent_name(), Some("spam"));
+
+        let torrent: Bencode = "d4:infod6:lengthi1eee".try_into().unwrap();
+        assert_eq!(torrent.torrent_name(), None);
+
+        let torrent: Bencode = "d4:name4:spame".try_into().unwrap();
+        assert_eq!(torrent.torrent_name(), None);
+    }
+
+    #[test]
+    fn test_info_bytes() {
+        let data = b"d8:announce5:udp:a4:infod6:pieces0:4:name4:spam6:lengthi1024eee";
+        let torrent: Bencode = data.as_ref().try_into().unwrap();
+
+        let right = b"d6:lengthi1024e4:name4:spam6:pieces0:e".to_vec();
+        assert_eq!(torrent.info_bytes(), Some(right));
+
+        let torrent: Bencode = "d4:info4:spame".try_into().unwrap();
+        assert_eq!(torrent.info_bytes(), None);
+        assert_eq!(Bencode::Integer(1).info_bytes(), None);
+    }
+
+    #[test]
+    fn test_raw_info_non_canonical() {
+        let data = b"d4:infod4:name4:spam6:lengthi1024eee";
+        let torrent: Bencode = data.as_ref().try_into().unwrap();
+
+        let raw = raw_info(data).unwrap().unwrap();
+        assert_eq!(raw, b"d4:name4:spam6:lengthi1024ee".as_ref());
+        assert_ne!(torrent.info_bytes().unwrap(), raw.to_vec());
+    }
+
+    #[test]
+    fn test_same_info() {
+        let a: Bencode = "d4:infod6:lengthi1e4:name4:spamee".try_into().unwrap();
+        let b: Bencode = "d7:comment3:foo4:infod6:lengthi1e4:name4:spamee"
+            .try_into()
+            .unwrap();
+        let c: Bencode = "d4:infod6:lengthi2e4:name4:spamee".try_into().unwrap();
+        let missing: Bencode = "d7:comment3:fooe".try_into().unwrap();
+
+        assert_eq!(same_info(&a, &b), Some(true));
+        assert_eq!(same_info(&a, &c), Some(false));
+        assert_eq!(same_info(&a, &missing), None);
+        assert_eq!(same_info(&missing, &a), None);
+    }
+
+    #[test]
+    fn test_piece_hashes() {
+        let pieces = format!("{}{}", "a".repeat(20), "b".repeat(20));
+        let data = format!("d4:infod6:lengthi1e4:name1:a6:pieces40:{}ee", pieces);
+        let value: Bencode = data.as_str().try_into().unwrap();
+        let torrent = TorrentFile::try_from(&value).unwrap();
+        assert_eq!(torrent.piece_hashes(), Ok(vec![[b'a'; 20], [b'b'; 20]]));
+
+        let torrent = TorrentFile {
+            pieces: Some(vec![0; 30]),
+            ..torrent
+        };
+        assert_eq!(torrent.piece_hashes(), Err(Error::InvalidPiecesLength(30)));
+
+        let torrent = TorrentFile {
+            pieces: None,
+            ..torrent
+        };
+        assert_eq!(torrent.piece_hashes(), Err(Error::MissingField("pieces")));
+    }
+
+    #[test]
+    fn test_is_valid_torrent() {
+        let torrent =
+            |info: &str| -> Bencode { format!("d4:info{}e", info).as_str().try_into().unwrap() };
+
+        assert!(torrent("d6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:e").is_valid_torrent());
+        assert!(torrent(
+            "d5:filesld6:lengthi1e4:pathl1:aeee4:name1:a12:piece lengthi1e6:pieces0:e"
+        )
+        .is_valid_torrent());
+
+        assert!(!torrent("d6:lengthi1e12:piece lengthi1e6:pieces0:e").is_valid_torrent());
+        assert!(!torrent("d6:lengthi1e4:name1:a6:pieces0:e").is_valid_torrent());
+        assert!(!torrent("d6:lengthi1e4:name1:a12:piece lengthi1ee").is_valid_torrent());
+        assert!(!torrent("d4:name1:a12:piece lengthi1e6:pieces0:e").is_valid_torrent());
+        assert!(!torrent("d6:lengthi1e4:namei1e12:piece lengthi1e6:pieces0:e").is_valid_torrent());
+        assert!(!torrent("le").is_valid_torrent());
+        assert!(!Bencode::Integer(1).is_valid_torrent());
+    }
+
+    #[test]
+    fn test_non_utf8_keys() {
+        let data = b"d13:creation datei1e4:infod5:filesld6:lengthi1e4:pathl1:ae1:\xffi0eee\
+            4:name1:a12:piece lengthi1e6:pieces0:1:\xffi0ee8:url-list1:u1:\xffi0ee";
+        let value = crate::from_bytes(data).unwrap();
+        assert!(matches!(value, Bencode::DictionaryBytes(_)));
+
+        assert!(value.is_valid_torrent());
+        assert_eq!(value.creation_date(), Some(1));
+        assert_eq!(value.web_seeds(), Some(vec!["u"]));
+
+        let torrent = TorrentFile::try_from(&value).unwrap();
+        let files = vec![FileEntry {
+            length: 1,
+            path: vec!["a".into()],
+        }];
+        assert_eq!(torrent.mode, TorrentMode::MultiFile { files });
+    }
+
+    #[test]
+    fn test_info_bytes_allocations() {
+        let files = (0..100).map(|i| {
+            Bencode::dict_from_iter(vec![
+                ("length".to_string(), Bencode::Integer(i)),
+                (
+                    "path".to_string(),
+                    Bencode::list_from_iter(vec![Bencode::from(format!("{}", i))]),
+                ),
+            ])
+        });
+        let info = Bencode::dict_from_iter(vec![
+            ("files".to_string(), Bencode::list_from_iter(files)),
+            ("name".to_string(), Bencode::from("dir".to_string())),
+            ("piece length".to_string(), Bencode::Integer(16384)),
+            ("pieces".to_string(), Bencode::ByteString(vec![0; 2000])),
+        ]);
+        let torrent = Bencode::dict_from_iter(vec![("info".to_string(), info)]);
+
+        // Encoding without capacity hint, from a clone of `info` dictionary.
+        let (expected, cloned) =
+            instrument::count(|| torrent.get("info").unwrap().clone().into_bytes());
+        let (actual, hinted) = instrument::count(|| torrent.info_bytes().unwrap());
+
+        assert_eq!(actual, expected);
+        assert!(hinted.allocations < cloned.allocations);
+        assert!(hinted.reallocations < cloned.reallocations);
+    }
+}
@@ -0,0 +1,566 @@
+//! Writer module contains data structures and procedures
+//! related to emitting bencoded data without building a tree.
+use crate::parser::Bencode;
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::{error, fmt};
+
+/// Represents possible complications that can occur during manual emission of data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Dictionary keys were not written in sorted order.
+    UnsortedKeys,
+    /// Value other than byte string was written as dictionary key.
+    InvalidKey,
+    /// Dictionary was closed right after a key, without its value.
+    MissingValue,
+    /// List was closed with `end_dict` or dictionary with `end_list`,
+    /// or container was closed while none was open.
+    MismatchedEnd,
+    /// Writing finished before all containers were closed.
+    UnclosedContainer,
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnsortedKeys => write!(f, "Dictionary keys were written in unsorted order."),
+            Error::InvalidKey => write!(f, "Dictionary key was not written as byte string."),
+            Error::MissingValue => write!(f, "Dictionary was closed before value of its key."),
+            Error::MismatchedEnd => write!(f, "Container was closed by end of different kind."),
+            Error::UnclosedContainer => write!(f, "Writing finished with containers left open."),
+        }
+    }
+}
+
+/// State of the container that is currently being written.
+struct Frame {
+    dictionary: bool,
+    /// Whether dictionary waits for a key rather than for a value.
+    expects_key: bool,
+    /// Whether `last_key` holds a key written into this dictionary.
+    has_key: bool,
+    /// Buffer of the most recent key, kept along with its allocation
+    /// when the frame is closed, to be reused by the next container at
+    /// the same depth.
+    last_key: Vec<u8>,
+}
+
+/// Emits bencoded values straight into the underlying writer.
+///
+/// # Examples
+///
+/// Writing dictionary with single integer.
+///
+/// ```
+/// use bensor::BencodeWriter;
+///
+/// let mut writer = BencodeWriter::new(Vec::new());
+/// writer.begin_dict().unwrap();
+/// writer.write_byte_string("answer").unwrap();
+/// writer.write_integer(42).unwrap();
+/// writer.end_dict().unwrap();
+///
+/// assert_eq!(writer.finish().unwrap(), b"d6:answeri42ee".to_vec());
+/// ```
+pub struct BencodeWriter<W: Write> {
+    writer: W,
+    /// Frames of open containers, followed by frames of closed ones,
+    /// which are kept for their key buffers.
+    frames: Vec<Frame>,
+    depth: usize,
+    error: Option<Error>,
+}
+
+impl<W: Write> BencodeWriter<W> {
+    /// Creates new writer emitting data into given `writer`.
+    pub fn new(writer: W) -> Self {
+        BencodeWriter {
+            writer,
+            frames: Vec::new(),
+            depth: 0,
+            error: None,
+        }
+    }
+
+    /// Returns frame of the container that is currently being written.
+    fn current(&mut self) -> Option<&mut Frame> {
+        match self.depth {
+            0 => None,
+            depth => Some(&mut self.frames[depth - 1]),
+        }
+    }
+
+    /// Records given complication, unless one has been recorded already.
+    fn fail(&mut self, err: Error) {
+        self.error.get_or_insert(err);
+    }
+
+    /// Marks that value other than byte string is about to be written
+    /// into current container.
+    fn begin_value(&mut self) {
+        if let Some(frame) = self.current() {
+            if frame.expects_key {
+                self.fail(Error::InvalidKey);
+            }
+            self.end_value();
+        }
+    }
+
+    /// Marks that value of the current dictionary has been written.
+    fn end_value(&mut self) {
+        if let Some(frame) = self.current() {
+            frame.expects_key = frame.dictionary;
+        }
+    }
+
+    /// Writes single bencoded integer.
+    pub fn write_integer(&mut self, n: i64) -> io::Result<()> {
+        self.begin_value();
+        write!(self.writer, "i{}e", n)
+    }
+
+    /// Writes single bencoded string of bytes. Inside dictionary
+    /// it is used for writing both keys and values.
+    pub fn write_byte_string(&mut self, s: impl AsRef<[u8]>) -> io::Result<()> {
+        let bytes = s.as_ref();
+        match self.current() {
+            Some(frame) if frame.expects_key => {
+                let unsorted = frame.has_key && frame.last_key.as_slice() >= bytes;
+                frame.last_key.clear();
+                frame.last_key.extend_from_slice(bytes);
+                frame.has_key = true;
+                frame.expects_key = false;
+                if unsorted {
+                    self.fail(Error::UnsortedKeys);
+                }
+            }
+            _ => self.end_value(),
+        }
+        write!(self.writer, "{}:", bytes.len())?;
+        self.writer.write_all(bytes)
+    }
+
+    /// Opens container of given kind, reusing frame of the container
+    /// which was closed at the same depth, if any.
+    fn begin(&mut self, dictionary: bool) {
+        self.begin_value();
+        if self.depth == self.frames.len() {
+            self.frames.push(Frame {
+                dictionary,
+                expects_key: dictionary,
+                has_key: false,
+                last_key: Vec::new(),
+            });
+        } else {
+            let frame = &mut self.frames[self.depth];
+            frame.dictionary = dictionary;
+            frame.expects_key = dictionary;
+            frame.has_key = false;
+        }
+        self.depth += 1;
+    }
+
+    /// Closes the most recently started container, which has to be of
+    /// given kind.
+    fn end(&mut self, dictionary: bool) {
+        match self.current() {
+            Some(frame) if frame.dictionary != dictionary => self.fail(Error::MismatchedEnd),
+            Some(frame) if frame.dictionary && !frame.expects_key => self.fail(Error::MissingValue),
+            Some(_) => {}
+            None => {
+                self.fail(Error::MismatchedEnd);
+                return;
+            }
+        }
+        self.depth -= 1;
+    }
+
+    /// Starts new dictionary. Every key has to be written
+    /// with `write_byte_string` before its value.
+    pub fn begin_dict(&mut self) -> io::Result<()> {
+        self.begin(true);
+        self.writer.write_all(b"d")
+    }
+
+    /// Closes the most recently started dictionary.
+    pub fn end_dict(&mut self) -> io::Result<()> {
+        self.end(true);
+        self.writer.write_all(b"e")
+    }
+
+    /// Starts new list.
+    pub fn begin_list(&mut self) -> io::Result<()> {
+        self.begin(false);
+        self.writer.write_all(b"l")
+    }
+
+    /// Closes the most recently started list.
+    pub fn end_list(&mut self) -> io::Result<()> {
+        self.end(false);
+        self.writer.write_all(b"e")
+    }
+
+    /// Writes whole `Bencode` tree, with dictionary keys in sorted order.
+    pub fn write_value(&mut self, value: &Bencode) -> io::Result<()> {
+        let mut stack = vec![Step::Value(value)];
+        while let Some(step) = stack.pop() {
+            let value = match step {
+                Step::Value(value) => value,
+                Step::Key(key) => {
+                    self.write_byte_string(key)?;
+                    continue;
+                }
+                Step::EndList => {
+                    self.end_list()?;
+                    continue;
+                }
+                Step::EndDict => {
+                    self.end_dict()?;
+                    continue;
+                }
+            };
+            match value {
+                Bencode::Integer(n) => self.write_integer(*n)?,
+                Bencode::ByteString(s) => self.write_byte_string(s)?,
+                Bencode::List(vec) => {
+                    self.begin_list()?;
+                    stack.push(Step::EndList);
+                    stack.extend(vec.iter().rev().map(Step::Value));
+                }
+                Bencode::Dictionary(map) => {
+                    let mut sorted_map = map
+                        .iter()
+                        .map(|(key, value)| (key.as_bytes(), value))
+                        .collect::<Vec<(&[u8], &Bencode)>>();
+                    sorted_map.sort_by_key(|(key, _)| *key);
+                    self.begin_dict()?;
+                    Step::push_entries(&mut stack, sorted_map);
+                }
+                Bencode::DictionaryBytes(map) => {
+                    let mut sorted_map = map
+                        .iter()
+                        .map(|(key, value)| (key.as_slice(), value))
+                        .collect::<Vec<(&[u8], &Bencode)>>();
+                    sorted_map.sort_by_key(|(key, _)| *key);
+                    self.begin_dict()?;
+                    Step::push_entries(&mut stack, sorted_map);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Returns the underlying writer, if the data written is valid:
+    /// every dictionary key is a byte string followed by its value and
+    /// greater than the key preceding it, and every container has been
+    /// closed by the end of its kind. Otherwise, returns the first
+    /// complication encountered.
+    pub fn finish(self) -> Result<W, Error> {
+        match self.error {
+            Some(err) => Err(err),
+            None if self.depth > 0 => Err(Error::UnclosedContainer),
+            None => Ok(self.writer),
+        }
+    }
+}
+
+/// Part of the value that is still to be written by `write_value`.
+enum Step<'a> {
+    Value(&'a Bencode),
+    Key(&'a [u8]),
+    EndList,
+    EndDict,
+}
+
+impl<'a> Step<'a> {
+    /// Pushes steps writing the rest of dictionary with given sorted
+    /// entries, in reverse order.
+    fn push_entries(stack: &mut Vec<Step<'a>>, sorted_map: Vec<(&'a [u8], &'a Bencode)>) {
+        stack.push(Step::EndDict);
+        for (key, value) in sorted_map.into_iter().rev() {
+            stack.push(Step::Value(value));
+            stack.push(Step::Key(key));
+        }
+    }
+}
+
+/// Part of the encoding that is still to be emitted by `ChunkEncoder`.
+enum Pending<'a> {
+    Value(&'a Bencode),
+    /// Bytes along with number of them emitted already.
+    Bytes(Cow<'a, [u8]>, usize),
+}
+
+/// Emits encoding of the value in chunks of bounded size, encoding
+/// only as much of the value as needed for the next chunk.
+///
+/// # Examples
+///
+/// ```
+/// use bensor::{Bencode, ChunkEncoder};
+///
+/// let value = Bencode::List(vec![Bencode::Integer(42), Bencode::ByteString(b"spam".to_vec())]);
+/// let chunks = ChunkEncoder::new(&value, 4).collect::<Vec<Vec<u8>>>();
+/// assert_eq!(chunks, vec![b"li42".to_vec(), b"e4:s".to_vec(), b"pame".to_vec()]);
+/// ```
+pub struct ChunkEncoder<'a> {
+    stack: Vec<Pending<'a>>,
+    chunk_len: usize,
+}
+
+impl<'a> ChunkEncoder<'a> {
+    /// Creates encoder emitting chunks of at most `chunk_len` bytes.
+    /// Every chunk but the last one is exactly `chunk_len` bytes long.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_len` is zero.
+    pub fn new(value: &'a Bencode, chunk_len: usize) -> Self {
+        assert!(chunk_len > 0, "chunk length must be greater than zero");
+        ChunkEncoder {
+            stack: vec![Pending::Value(value)],
+            chunk_len,
+        }
+    }
+
+    /// Pushes parts of given value onto the stack, in reverse order.
+    fn expand(&mut self, value: &'a Bencode) {
+        let prefixed = |stack: &mut Vec<Pending<'a>>, bytes: &'a [u8]| {
+            stack.push(Pending::Bytes(Cow::Borrowed(bytes), 0));
+            let prefix = format!("{}:", bytes.len()).into_bytes();
+            stack.push(Pending::Bytes(Cow::Owned(prefix), 0));
+        };
+        match value {
+            Bencode::Integer(n) => {
+                let bytes = format!("i{}e", n).into_bytes();
+                self.stack.push(Pending::Bytes(Cow::Owned(bytes), 0));
+            }
+            Bencode::ByteString(s) => prefixed(&mut self.stack, s),
+            Bencode::List(vec) => {
+                self.stack.push(Pending::Bytes(Cow::Borrowed(b"e"), 0));
+                self.stack.extend(vec.iter().rev().map(Pending::Value));
+                self.stack.push(Pending::Bytes(Cow::Borrowed(b"l"), 0));
+            }
+            Bencode::Dictionary(map) => {
+                let mut sorted_map = map
+                    .iter()
+                    .map(|(key, value)| (key.as_bytes(), value))
+                    .collect::<Vec<(&[u8], &Bencode)>>();
+                sorted_map.sort_by_key(|(key, _)| *key);
+
+                self.stack.push(Pending::Bytes(Cow::Borrowed(b"e"), 0));
+                for (key, value) in sorted_map.into_iter().rev() {
+                    self.stack.push(Pending::Value(value));
+                    prefixed(&mut self.stack, key);
+                }
+                self.stack.push(Pending::Bytes(Cow::Borrowed(b"d"), 0));
+            }
+            Bencode::DictionaryBytes(map) => {
+                let mut sorted_map = map
+                    .iter()
+                    .map(|(key, value)| (key.as_slice(), value))
+                    .collect::<Vec<(&[u8], &Bencode)>>();
+                sorted_map.sort_by_key(|(key, _)| *key);
+
+                self.stack.push(Pending::Bytes(Cow::Borrowed(b"e"), 0));
+                for (key, value) in sorted_map.into_iter().rev() {
+                    self.stack.push(Pending::Value(value));
+                    prefixed(&mut self.stack, key);
+                }
+                self.stack.push(Pending::Bytes(Cow::Borrowed(b"d"), 0));
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for ChunkEncoder<'a> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::with_capacity(self.chunk_len);
+        while chunk.len() < self.chunk_len {
+            match self.stack.pop() {
+                Some(Pending::Value(value)) => self.expand(value),
+                Some(Pending::Bytes(bytes, emitted)) => {
+                    let len = (bytes.len() - emitted).min(self.chunk_len - chunk.len());
+                    chunk.extend_from_slice(&bytes[emitted..emitted + len]);
+                    if emitted + len < bytes.len() {
+                        self.stack.push(Pending::Bytes(bytes, emitted + len));
+                    }
+                }
+                None => break,
+            }
+        }
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_write_dict() {
+        let left = {
+            let mut writer = BencodeWriter::new(Vec::new());
+            writer.begin_dict().unwrap();
+            writer.write_byte_string("list").unwrap();
+            writer.begin_list().unwrap();
+            writer.write_integer(1).unwrap();
+            writer.write_byte_string("two").unwrap();
+            writer.end_list().unwrap();
+            writer.write_byte_string("number").unwrap();
+            writer.write_integer(-42).unwrap();
+            writer.end_dict().unwrap();
+            writer.finish().unwrap()
+        };
+
+        let right = {
+            let mut h = HashMap::new();
+            h.insert(
+                "list".into(),
+                Bencode::List(vec![Bencode::Integer(1), Bencode::ByteString("two".into())]),
+            );
+            h.insert("number".into(), Bencode::Integer(-42));
+            Bencode::Dictionary(h).into_bytes()
+        };
+
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn test_write_value() {
+        let value = {
+            let mut h = HashMap::new();
+            h.insert("b".into(), Bencode::Integer(2));
+            h.insert("a".into(), Bencode::ByteString("one".into()));
+            Bencode::Dictionary(h)
+        };
+
+        let mut left = Vec::new();
+        value.write_to(&mut left).unwrap();
+
+        assert_eq!(left, value.into_bytes());
+    }
+
+    #[test]
+    fn test_write_deeply_nested_value() {
+        let depth = 100_000;
+        let mut value = Bencode::Integer(0);
+        for _ in 0..depth {
+            value = Bencode::List(vec![value]);
+        }
+
+        let mut writer = BencodeWriter::new(Vec::new());
+        writer.write_value(&value).unwrap();
+        let data = writer.finish().unwrap();
+
+        assert_eq!(data.len(), 2 * depth + 3);
+        assert_eq!(data, value.into_bytes());
+    }
+
+    #[test]
+    fn test_write_unsorted_keys() {
+        let mut writer = BencodeWriter::new(Vec::new());
+        writer.begin_dict().unwrap();
+        writer.write_byte_string("number").unwrap();
+        writer.write_integer(-42).unwrap();
+        writer.write_byte_string("list").unwrap();
+        writer.begin_list().unwrap();
+        writer.end_list().unwrap();
+        writer.end_dict().unwrap();
+
+        assert_eq!(writer.finish(), Err(Error::UnsortedKeys));
+    }
+
+    #[test]
+    fn test_write_invalid_structure() {
+        let finish = |write: &dyn Fn(&mut BencodeWriter<Vec<u8>>) -> io::Result<()>| {
+            let mut writer = BencodeWriter::new(Vec::new());
+            write(&mut writer).unwrap();
+            writer.finish()
+        };
+
+        let invalid_key = finish(&|writer| {
+            writer.begin_dict()?;
+            writer.write_integer(1)?;
+            writer.end_list()
+        });
+        assert_eq!(invalid_key, Err(Error::InvalidKey));
+
+        let missing_value = finish(&|writer| {
+            writer.begin_dict()?;
+            writer.write_byte_string("key")?;
+            writer.end_dict()
+        });
+        assert_eq!(missing_value, Err(Error::MissingValue));
+
+        let mismatched = finish(&|writer| {
+            writer.begin_list()?;
+            writer.end_dict()
+        });
+        assert_eq!(mismatched, Err(Error::MismatchedEnd));
+        let unopened = finish(&|writer| writer.end_list());
+        assert_eq!(unopened, Err(Error::MismatchedEnd));
+
+        let unclosed = finish(&|writer| {
+            writer.begin_list()?;
+            writer.begin_dict()?;
+            writer.end_dict()
+        });
+        assert_eq!(unclosed, Err(Error::UnclosedContainer));
+
+        // Keys of sibling dictionaries are compared only within each of them.
+        let siblings = finish(&|writer| {
+            writer.begin_list()?;
+            for _ in 0..2 {
+                writer.begin_dict()?;
+                writer.write_byte_string("a")?;
+                writer.begin_list()?;
+                writer.end_list()?;
+                writer.write_byte_string("b")?;
+                writer.write_integer(1)?;
+                writer.end_dict()?;
+            }
+            writer.end_list()
+        });
+        assert_eq!(siblings, Ok(b"ld1:ale1:bi1eed1:ale1:bi1eee".to_vec()));
+    }
+
+    #[test]
+    fn test_chunk_encoder() {
+        let value = {
+            let mut h = HashMap::new();
+            h.insert("pieces".into(), Bencode::ByteString(vec![0xff; 100]));
+            h.insert(
+                "files".into(),
+                Bencode::List((0..50).map(Bencode::Integer).collect()),
+            );
+            h.insert("name".into(), Bencode::ByteString("spam".into()));
+            Bencode::Dictionary(h)
+        };
+        let right = value.clone().into_bytes();
+
+        for chunk_len in &[1, 3, 7, 64, right.len(), right.len() + 1] {
+            let chunks = ChunkEncoder::new(&value, *chunk_len).collect::<Vec<Vec<u8>>>();
+            let (last, full) = chunks.split_last().unwrap();
+            assert!(full.iter().all(|chunk| chunk.len() == *chunk_len));
+            assert!(!last.is_empty() && last.len() <= *chunk_len);
+            assert_eq!(chunks.concat(), right);
+        }
+    }
+}